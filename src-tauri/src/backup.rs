@@ -0,0 +1,150 @@
+use crate::database::{self, Database};
+use crate::models::BackupInfo;
+use rusqlite::{backup::Progress, Connection, DatabaseName};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+// Copies of the database taken with SQLite's online backup API, so they are
+// consistent even while the app keeps writing. They go in a backups folder
+// next to the database, named after when (UTC) and why they were taken:
+//
+//     kiroshi-20261019-142530.123-before_import.db
+
+pub const STARTUP: &str = "startup";
+pub const BEFORE_MIGRATION: &str = "before_migration";
+pub const BEFORE_IMPORT: &str = "before_import";
+pub const RACE_LOCKED: &str = "race_locked";
+pub const PERIODIC: &str = "periodic";
+pub const BEFORE_RESTORE: &str = "before_restore";
+pub const BEFORE_REPAIR: &str = "before_repair";
+pub const MANUAL: &str = "manual";
+
+const REASONS: &[&str] = &[
+    STARTUP,
+    BEFORE_MIGRATION,
+    BEFORE_IMPORT,
+    RACE_LOCKED,
+    PERIODIC,
+    BEFORE_RESTORE,
+    BEFORE_REPAIR,
+    MANUAL,
+];
+
+// Each reason keeps its own last files, so a busy afternoon of periodic
+// backups does not push out the one taken before an import
+const KEPT_PER_REASON: usize = 10;
+
+// Periodic backups are only taken when something changed since the last one
+const PERIODIC_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+// Reads the time and reason back from a backup file name, None for any other file
+fn parse_file_name(file_name: &str) -> Option<(String, String)> {
+    let stem = file_name.strip_prefix("kiroshi-")?.strip_suffix(".db")?;
+    let mut parts = stem.splitn(3, '-');
+    let (date, time, reason) = (parts.next()?, parts.next()?, parts.next()?);
+    let (time, millis) = time.split_once('.')?;
+    let digits = |value: &str, len: usize| value.len() == len && value.bytes().all(|b| b.is_ascii_digit());
+    if !digits(date, 8) || !digits(time, 6) || !digits(millis, 3) || !REASONS.contains(&reason) {
+        return None;
+    }
+
+    let created_at = format!(
+        "{}-{}-{} {}:{}:{}",
+        &date[..4],
+        &date[4..6],
+        &date[6..],
+        &time[..2],
+        &time[2..4],
+        &time[4..]
+    );
+    Some((created_at, reason.to_string()))
+}
+
+fn backup_info(path: &Path) -> Option<BackupInfo> {
+    let file_name = path.file_name()?.to_str()?.to_string();
+    let (created_at, reason) = parse_file_name(&file_name)?;
+    let size_bytes = fs::metadata(path).ok()?.len();
+    Some(BackupInfo { file_name, reason, created_at, size_bytes })
+}
+
+// Newest first
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups: Vec<BackupInfo> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| backup_info(&entry.ok()?.path()))
+        .collect();
+    // The time leads the file name, so names sort in time order
+    backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    Ok(backups)
+}
+
+pub fn create(conn: &Connection, dir: &Path, reason: &str) -> Result<BackupInfo, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let stamp: String = conn
+        .query_row("SELECT strftime('%Y%m%d-%H%M%f', 'now')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let path = dir.join(format!("kiroshi-{}-{}.db", stamp, reason));
+    conn.backup(DatabaseName::Main, &path, None)
+        .map_err(|e| format!("Could not back up the database: {}", e))?;
+
+    for old in list(dir)?.iter().filter(|backup| backup.reason == reason).skip(KEPT_PER_REASON) {
+        fs::remove_file(dir.join(&old.file_name)).map_err(|e| e.to_string())?;
+    }
+
+    backup_info(&path).ok_or_else(|| format!("Backup {} was not written", path.display()))
+}
+
+fn backup_path(dir: &Path, file_name: &str) -> Result<PathBuf, String> {
+    // Only names the app wrote, which also keeps the path inside the folder
+    let path = dir.join(file_name);
+    if parse_file_name(file_name).is_none() || !path.is_file() {
+        return Err(format!("Backup not found: {}", file_name));
+    }
+    Ok(path)
+}
+
+// Loads a backup in memory, with the schema brought up to date
+pub fn open(dir: &Path, file_name: &str) -> Result<Connection, String> {
+    let path = backup_path(dir, file_name)?;
+    let mut conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "foreign_keys", true).map_err(|e| e.to_string())?;
+    conn.restore(DatabaseName::Main, &path, None::<fn(Progress)>)
+        .map_err(|e| e.to_string())?;
+    database::prepare_schema(&conn).map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+// Replaces the live database with a backup
+pub fn restore(conn: &mut Connection, dir: &Path, file_name: &str) -> Result<(), String> {
+    let path = backup_path(dir, file_name)?;
+    conn.restore(DatabaseName::Main, &path, None::<fn(Progress)>)
+        .map_err(|e| e.to_string())?;
+    database::prepare_schema(conn).map_err(|e| e.to_string())
+}
+
+pub fn start_periodic_thread(app_handle: AppHandle) {
+    thread::spawn(move || {
+        let db = app_handle.state::<Database>();
+        let mut saved_changes = db.conn.lock().map(|conn| conn.total_changes()).ok();
+
+        loop {
+            thread::sleep(PERIODIC_INTERVAL);
+
+            let Ok(conn) = db.conn.lock() else {
+                break;
+            };
+            let changes = conn.total_changes();
+            // Tried again on the next round when it fails
+            if saved_changes != Some(changes) && create(&conn, &db.backup_dir, PERIODIC).is_ok() {
+                saved_changes = Some(changes);
+            }
+        }
+    });
+}
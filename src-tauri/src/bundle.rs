@@ -0,0 +1,49 @@
+use crate::models::{PC, Race, ReferenceEntry, ReferenceTulip};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Race bundle: a whole race as a single JSON document, to hand a prepared
+// roadbook to another crew. Ids inside the bundle are the ones of the
+// exporting machine and are remapped on import; races are matched by `uid`.
+
+pub const BUNDLE_FORMAT: &str = "kiroshi-race";
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundlePC {
+    pub pc: PC,
+    pub references: Vec<ReferenceEntry>,
+    pub tulips: Vec<ReferenceTulip>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaceBundle {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    pub race: Race,
+    pub pcs: Vec<BundlePC>,
+    // Per-race settings, keyed without the `race.<id>.` prefix
+    pub settings: HashMap<String, String>,
+}
+
+// Preferences stored under this prefix belong to a single race
+pub fn race_setting_prefix(race_id: i64) -> String {
+    format!("race.{}.", race_id)
+}
+
+pub fn parse(json: &str) -> Result<RaceBundle, String> {
+    let bundle: RaceBundle = serde_json::from_str(json).map_err(|e| format!("Invalid race bundle: {}", e))?;
+
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("Not a race bundle ({})", bundle.format));
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!(
+            "The race bundle is version {}, this version of Kiroshi reads up to {}",
+            bundle.version, BUNDLE_VERSION
+        ));
+    }
+
+    Ok(bundle)
+}
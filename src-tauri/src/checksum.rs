@@ -0,0 +1,195 @@
+use crate::models::{PCChecksum, RaceChecksum, ReferenceEntry, PC};
+use sha2::{Digest, Sha256};
+
+// Roadbook checksum, to confirm two machines loaded the same references.
+// Only what changes the timing goes into the hash, written out as text in
+// roadbook order: ids, order index values, creation dates, notes, landmarks
+// and tulips differ between crews and are left out. Distances computed from
+// the times are left out too, the ones typed in are kept.
+
+// Crockford's base32, without letters that read like digits
+const FINGERPRINT_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+fn canonical_number(value: Option<f64>) -> String {
+    // -0.0 and 0.0 must hash the same
+    value.map(|v| format!("{}", v + 0.0)).unwrap_or_default()
+}
+
+fn canonical_pc(pc: &PC, references: &[ReferenceEntry]) -> String {
+    let optional = |value: Option<i64>| value.map(|v| v.to_string()).unwrap_or_default();
+    let mut text = format!(
+        "pc|{}|{}|{}|{}|{}\n",
+        pc.pc_number,
+        pc.pc_type,
+        optional(pc.scheduled_start_centiseconds),
+        optional(pc.target_time_centiseconds),
+        optional(pc.total_distance_meters),
+    );
+
+    for reference in references {
+        let typed_distance = if reference.defined_by == "distance" { reference.distance_meters } else { None };
+        text.push_str(&format!(
+            "ref|{}|{}|{}|{}|{}|{}|{}\n",
+            reference.time_centiseconds(),
+            reference.event_type,
+            reference.speed,
+            canonical_number(reference.extra_value),
+            u8::from(reference.is_control_zone),
+            reference.defined_by,
+            canonical_number(typed_distance),
+        ));
+    }
+
+    text
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// First 40 bits of the hash as eight characters, "K7QD-3M9X"
+pub fn fingerprint(hash: &str) -> String {
+    let bits = u64::from_str_radix(&hash[..10], 16).unwrap_or(0);
+    let characters: String = (0..8)
+        .rev()
+        .map(|i| FINGERPRINT_ALPHABET[(bits >> (i * 5) & 31) as usize] as char)
+        .collect();
+    format!("{}-{}", &characters[..4], &characters[4..])
+}
+
+// Fingerprints typed in by hand: case and separators don't matter, and the
+// letters Crockford's alphabet leaves out are read as the digits they resemble
+pub fn normalize_fingerprint(value: &str) -> String {
+    let characters: String = value
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+    if characters.len() == 8 {
+        format!("{}-{}", &characters[..4], &characters[4..])
+    } else {
+        characters
+    }
+}
+
+pub fn pc_checksum(pc: &PC, references: &[ReferenceEntry]) -> PCChecksum {
+    let hash = hex(&Sha256::digest(canonical_pc(pc, references)));
+    PCChecksum {
+        pc_id: pc.id,
+        pc_number: pc.pc_number,
+        reference_count: references.len(),
+        fingerprint: fingerprint(&hash),
+        hash,
+    }
+}
+
+// The race hash covers the PC hashes in PC number order
+pub fn race_checksum(race_id: i64, mut pcs: Vec<PCChecksum>) -> RaceChecksum {
+    pcs.sort_by_key(|pc| pc.pc_number);
+    let mut hasher = Sha256::new();
+    for pc in &pcs {
+        hasher.update(format!("{}|{}\n", pc.pc_number, pc.hash));
+    }
+    let hash = hex(&hasher.finalize());
+
+    RaceChecksum {
+        race_id,
+        fingerprint: fingerprint(&hash),
+        hash,
+        pcs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn pc() -> PC {
+        PC {
+            id: 1,
+            race_id: 1,
+            pc_number: 3,
+            name: None,
+            pc_type: "regularity".to_string(),
+            start_location: None,
+            scheduled_start_centiseconds: Some(36000000),
+            target_time_centiseconds: None,
+            total_distance_meters: None,
+            notes: None,
+            deleted_at: None,
+            created_at: String::new(),
+        }
+    }
+
+    fn reference(id: i64, event_type: &str, time: i64) -> ReferenceEntry {
+        test_support::reference(id, event_type, time, 45000)
+    }
+
+    #[test]
+    fn fingerprint_takes_the_first_forty_bits() {
+        assert_eq!(fingerprint("0000000000ffff"), "0000-0000");
+        assert_eq!(fingerprint("0842108421ffff"), "1111-1111");
+        assert_eq!(fingerprint("ffffffffff0000"), "ZZZZ-ZZZZ");
+    }
+
+    #[test]
+    fn hand_typed_fingerprints_normalize_to_the_same_value() {
+        let checksum = pc_checksum(&pc(), &[reference(1, "LAR", 3600000), reference(2, "REF", 3606000)]);
+        let typed = checksum.fingerprint.as_str();
+        for variant in [
+            typed.to_string(),
+            typed.to_lowercase(),
+            typed.replace('-', ""),
+            typed.replace('-', " "),
+            format!(" {} ", typed.replace('-', " - ")),
+            typed.replace('0', "O").replace('1', "l"),
+            typed.replace('0', "o").replace('1', "I"),
+        ] {
+            assert_eq!(normalize_fingerprint(&variant), typed, "{:?}", variant);
+        }
+        assert_eq!(normalize_fingerprint("il1L-OoO0"), "1111-0000");
+        // Anything but eight characters is left for the caller to reject
+        assert_eq!(normalize_fingerprint("ab-c"), "ABC");
+    }
+
+    #[test]
+    fn only_timing_fields_change_the_checksum() {
+        let references = vec![reference(1, "LAR", 3600000), reference(2, "REF", 3606000)];
+        let base = pc_checksum(&pc(), &references);
+
+        // Ids, notes, landmarks, tulips and computed distances differ between crews
+        let mut other_pc = pc();
+        other_pc.id = 9;
+        other_pc.notes = Some("copiado".to_string());
+        let mut other = references.clone();
+        other[1].id = 40;
+        other[1].order_index = 7;
+        other[1].note = Some("ojo".to_string());
+        other[1].landmark = Some("puente".to_string());
+        other[1].has_tulip = true;
+        other[1].distance_meters = Some(750.0);
+        assert_eq!(pc_checksum(&other_pc, &other).hash, base.hash);
+
+        other[1].extra_value = Some(-0.0);
+        let negative_zero = pc_checksum(&pc(), &other).hash;
+        other[1].extra_value = Some(0.0);
+        assert_eq!(pc_checksum(&pc(), &other).hash, negative_zero);
+
+        let mut slower = references.clone();
+        slower[1].speed = 44000;
+        assert_ne!(pc_checksum(&pc(), &slower).hash, base.hash);
+
+        // A distance typed in is part of the roadbook
+        let mut typed = references;
+        typed[1].defined_by = "distance".to_string();
+        typed[1].distance_meters = Some(750.0);
+        let typed_hash = pc_checksum(&pc(), &typed).hash;
+        typed[1].distance_meters = Some(760.0);
+        assert_ne!(pc_checksum(&pc(), &typed).hash, typed_hash);
+    }
+}
@@ -1,24 +1,96 @@
+use crate::backup;
+use crate::bundle::{self, BundlePC, RaceBundle};
+use crate::checksum;
 use crate::database::Database;
-use crate::models::{CreateReferenceRequest, PC, Race, ReferenceEntry, UpdateReferenceRequest};
+use crate::error::CommandError;
+use crate::models::{
+    BackupInfo, BackupRace, Bulletin, BundlePCConflict, BulletinOperation, BulletinPCChange, BulletinPreview, BulletinUndo,
+    CreateRaceRequest, CreateReferenceRequest, DuplicateOptions, EditHistoryEntry, EditState,
+    ImportTemplate, IntegrityIssue, IntegrityReport, MarchRow,
+    MarchTableOptions, PCEditState, PCRevisionDiff, PCSnapshot, PdfCandidate, PdfImportOptions,
+    PCChecksum, PCChecksumComparison, PdfImportPreview, PC, QrChunk, QrTransferStatus, Race,
+    RaceChecksum, RaceEditState, RaceBundleImportReport, RaceLockEvent, ReferenceEntry,
+    ReferenceTulip, Revision, RoadbookImportReport, RoadbookLineError, RouteInterval,
+    SaveBulletinRequest, SaveImportTemplateRequest, UpdatePCRequest, UpdateRaceRequest,
+    TrashItem, UpdateReferenceRequest, ValidationReport, XlsxImportOptions, XlsxSheetPreview,
+};
+use crate::march_table;
+use crate::pdf_import;
+use crate::qr_transfer;
+use crate::roadbook;
+use crate::revisions;
+use crate::route;
+use crate::validation;
+use crate::xlsx;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use tauri::State;
 
 // ==================== RACE COMMANDS ====================
 
+const RACE_COLUMNS: &str = "id, uid, name, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes, locked_at, deleted_at, created_at";
+
+fn race_from_row(row: &rusqlite::Row) -> rusqlite::Result<Race> {
+    Ok(Race {
+        id: row.get(0)?,
+        uid: row.get(1)?,
+        name: row.get(2)?,
+        event_date: row.get(3)?,
+        event_end_date: row.get(4)?,
+        location: row.get(5)?,
+        organizer: row.get(6)?,
+        category: row.get(7)?,
+        car_number: row.get(8)?,
+        driver_name: row.get(9)?,
+        navigator_name: row.get(10)?,
+        notes: row.get(11)?,
+        locked_at: row.get(12)?,
+        deleted_at: row.get(13)?,
+        created_at: row.get(14)?,
+    })
+}
+
+fn query_race(conn: &Connection, id: i64) -> Result<Race, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM races WHERE id = ?1", RACE_COLUMNS),
+        [id],
+        race_from_row,
+    )
+    .map_err(|e| e.to_string())
+}
+
+// `search` matches name, venue, organiser, category, crew and car number.
+// `sort` is "name", "event_date" or, by default, most recently created first.
 #[tauri::command]
-pub fn get_all_races(db: State<Database>) -> Result<Vec<Race>, String> {
+pub fn get_all_races(
+    db: State<Database>,
+    search: Option<String>,
+    sort: Option<String>,
+) -> Result<Vec<Race>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    let order_by = match sort.as_deref() {
+        Some("name") => "name COLLATE NOCASE ASC",
+        Some("event_date") => "event_date DESC NULLS LAST, created_at DESC",
+        _ => "created_at DESC",
+    };
+    let pattern = format!("%{}%", search.unwrap_or_default().trim());
+
     let mut stmt = conn
-        .prepare("SELECT id, name, created_at FROM races ORDER BY created_at DESC")
+        .prepare(&format!(
+            "SELECT {} FROM races
+             WHERE deleted_at IS NULL
+               AND (name LIKE ?1 OR location LIKE ?1 OR organizer LIKE ?1 OR category LIKE ?1
+                    OR driver_name LIKE ?1 OR navigator_name LIKE ?1 OR car_number LIKE ?1)
+             ORDER BY {}",
+            RACE_COLUMNS, order_by
+        ))
         .map_err(|e| e.to_string())?;
 
     let races = stmt
-        .query_map([], |row| {
-            Ok(Race {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                created_at: row.get(2)?,
-            })
-        })
+        .query_map([&pattern], race_from_row)
         .map_err(|e| e.to_string())?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.to_string())?;
@@ -29,157 +101,350 @@ pub fn get_all_races(db: State<Database>) -> Result<Vec<Race>, String> {
 #[tauri::command]
 pub fn get_race(db: State<Database>, id: i64) -> Result<Race, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, name, created_at FROM races WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+    query_race(&conn, id)
+}
 
-    stmt.query_row([id], |row| {
-        Ok(Race {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+#[tauri::command]
+pub fn create_race(db: State<Database>, request: CreateRaceRequest) -> Result<Race, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO races (name, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        rusqlite::params![
+            request.name,
+            request.event_date,
+            request.event_end_date,
+            request.location,
+            request.organizer,
+            request.category,
+            request.car_number,
+            request.driver_name,
+            request.navigator_name,
+            request.notes
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let id = tx.last_insert_rowid();
+    record_race_revision(&tx, id, "create_race")?;
+    let mut before = EditState::default();
+    before.created_race(id, false);
+    record_edit(&tx, "create_race", before)?;
+    let race = query_race(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(race)
 }
 
 #[tauri::command]
-pub fn create_race(db: State<Database>, name: String) -> Result<Race, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO races (name) VALUES (?1)",
-        [&name],
+pub fn update_race(db: State<Database>, request: UpdateRaceRequest) -> Result<Race, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&tx, request.id)?;
+    let before = capture_edit(&tx, &[request.id], &[], false)?;
+    tx.execute(
+        "UPDATE races
+         SET name = ?1, event_date = ?2, event_end_date = ?3, location = ?4, organizer = ?5,
+             category = ?6, car_number = ?7, driver_name = ?8, navigator_name = ?9, notes = ?10
+         WHERE id = ?11",
+        rusqlite::params![
+            request.name,
+            request.event_date,
+            request.event_end_date,
+            request.location,
+            request.organizer,
+            request.category,
+            request.car_number,
+            request.driver_name,
+            request.navigator_name,
+            request.notes,
+            request.id
+        ],
     )
     .map_err(|e| e.to_string())?;
 
-    let id = conn.last_insert_rowid();
-    let mut stmt = conn
-        .prepare("SELECT id, name, created_at FROM races WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+    record_race_revision(&tx, request.id, "update_race")?;
+    record_edit(&tx, "update_race", before)?;
+    let race = query_race(&tx, request.id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(race)
+}
+
+// Moves the race to the trash, its PCs and references stay with it
+#[tauri::command]
+pub fn delete_race(db: State<Database>, id: i64) -> Result<(), CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&tx, id)?;
+    let before = capture_edit(&tx, &[id], &[], false)?;
+    tx.execute(
+        "UPDATE races SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+    record_edit(&tx, "delete_race", before)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ==================== RACE LOCK COMMANDS ====================
 
-    stmt.query_row([id], |row| {
-        Ok(Race {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
+fn ensure_race_unlocked(conn: &Connection, race_id: i64) -> Result<(), CommandError> {
+    let (name, locked_at): (String, Option<String>) = conn
+        .query_row("SELECT name, locked_at FROM races WHERE id = ?1", [race_id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
         })
-    })
-    .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+
+    match locked_at {
+        Some(_) => Err(CommandError::RaceLocked(name)),
+        None => Ok(()),
+    }
 }
 
-#[tauri::command]
-pub fn update_race(db: State<Database>, id: i64, name: String) -> Result<Race, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute("UPDATE races SET name = ?1 WHERE id = ?2", [&name, &id.to_string()])
+fn ensure_pc_unlocked(conn: &Connection, pc_id: i64) -> Result<(), CommandError> {
+    let race_id: i64 = conn
+        .query_row("SELECT race_id FROM pcs WHERE id = ?1", [pc_id], |row| row.get(0))
         .map_err(|e| e.to_string())?;
+    ensure_race_unlocked(conn, race_id)
+}
 
-    let mut stmt = conn
-        .prepare("SELECT id, name, created_at FROM races WHERE id = ?1")
+fn ensure_reference_unlocked(conn: &Connection, reference_id: i64) -> Result<(), CommandError> {
+    let race_id: i64 = conn
+        .query_row(
+            "SELECT pcs.race_id FROM reference_entries JOIN pcs ON pcs.id = reference_entries.pc_id
+             WHERE reference_entries.id = ?1",
+            [reference_id],
+            |row| row.get(0),
+        )
         .map_err(|e| e.to_string())?;
+    ensure_race_unlocked(conn, race_id)
+}
 
-    stmt.query_row([id], |row| {
-        Ok(Race {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+// A whole database restore would overwrite every locked race
+fn ensure_no_race_locked(conn: &Connection) -> Result<(), CommandError> {
+    let locked: Option<String> = conn
+        .query_row(
+            "SELECT name FROM races WHERE locked_at IS NOT NULL ORDER BY id LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match locked {
+        Some(name) => Err(CommandError::RaceLocked(name)),
+        None => Ok(()),
+    }
+}
+
+fn set_race_lock(conn: &mut Connection, race_id: i64, locked: bool, reason: Option<String>) -> Result<Race, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let race = query_race(&tx, race_id)?;
+    if race.locked_at.is_some() == locked {
+        return Ok(race);
+    }
+
+    tx.execute(
+        "UPDATE races SET locked_at = CASE WHEN ?1 THEN datetime('now') END WHERE id = ?2",
+        rusqlite::params![locked, race_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO race_lock_events (race_id, action, reason) VALUES (?1, ?2, ?3)",
+        rusqlite::params![race_id, if locked { "lock" } else { "unlock" }, reason],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let race = query_race(&tx, race_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(race)
 }
 
+// While locked, the roadbook of the race (references, PCs, bulletins,
+// imports and restores) cannot be changed
 #[tauri::command]
-pub fn delete_race(db: State<Database>, id: i64) -> Result<(), String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    // Foreign key cascade will delete associated PCs and references
-    conn.execute("DELETE FROM races WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
-    Ok(())
+pub fn lock_race(db: State<Database>, race_id: i64, reason: Option<String>) -> Result<Race, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    // The roadbook as it goes into competition. SQLite cannot back up inside
+    // the lock transaction, so it is taken first: a failed backup leaves the
+    // race unlocked instead of locked with an error
+    if query_race(&conn, race_id)?.locked_at.is_none() {
+        backup::create(&conn, &db.backup_dir, backup::RACE_LOCKED)?;
+    }
+    set_race_lock(&mut conn, race_id, true, reason)
 }
 
-// ==================== PC COMMANDS ====================
+#[tauri::command]
+pub fn unlock_race(db: State<Database>, race_id: i64, reason: Option<String>) -> Result<Race, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    set_race_lock(&mut conn, race_id, false, reason)
+}
 
+// Newest first
 #[tauri::command]
-pub fn get_pcs_by_race(db: State<Database>, race_id: i64) -> Result<Vec<PC>, String> {
+pub fn get_race_lock_events(db: State<Database>, race_id: i64) -> Result<Vec<RaceLockEvent>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
     let mut stmt = conn
-        .prepare("SELECT id, race_id, pc_number, created_at FROM pcs WHERE race_id = ?1 ORDER BY pc_number ASC")
+        .prepare(
+            "SELECT id, race_id, action, reason, created_at FROM race_lock_events
+             WHERE race_id = ?1 ORDER BY id DESC",
+        )
         .map_err(|e| e.to_string())?;
 
-    let pcs = stmt
+    let events = stmt
         .query_map([race_id], |row| {
-            Ok(PC {
+            Ok(RaceLockEvent {
                 id: row.get(0)?,
                 race_id: row.get(1)?,
-                pc_number: row.get(2)?,
-                created_at: row.get(3)?,
+                action: row.get(2)?,
+                reason: row.get(3)?,
+                created_at: row.get(4)?,
             })
         })
         .map_err(|e| e.to_string())?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.to_string())?;
 
-    Ok(pcs)
+    Ok(events)
 }
 
-#[tauri::command]
-pub fn get_pc(db: State<Database>, id: i64) -> Result<PC, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+// ==================== PC COMMANDS ====================
+
+const PC_COLUMNS: &str = "id, race_id, pc_number, name, pc_type, start_location, scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes, deleted_at, created_at";
+
+fn pc_from_row(row: &rusqlite::Row) -> rusqlite::Result<PC> {
+    Ok(PC {
+        id: row.get(0)?,
+        race_id: row.get(1)?,
+        pc_number: row.get(2)?,
+        name: row.get(3)?,
+        pc_type: row.get(4)?,
+        start_location: row.get(5)?,
+        scheduled_start_centiseconds: row.get(6)?,
+        target_time_centiseconds: row.get(7)?,
+        total_distance_meters: row.get(8)?,
+        notes: row.get(9)?,
+        deleted_at: row.get(10)?,
+        created_at: row.get(11)?,
+    })
+}
+
+fn query_pcs(conn: &Connection, race_id: i64) -> Result<Vec<PC>, String> {
     let mut stmt = conn
-        .prepare("SELECT id, race_id, pc_number, created_at FROM pcs WHERE id = ?1")
+        .prepare(&format!(
+            "SELECT {} FROM pcs WHERE race_id = ?1 AND deleted_at IS NULL ORDER BY pc_number ASC",
+            PC_COLUMNS
+        ))
         .map_err(|e| e.to_string())?;
 
-    stmt.query_row([id], |row| {
-        Ok(PC {
-            id: row.get(0)?,
-            race_id: row.get(1)?,
-            pc_number: row.get(2)?,
-            created_at: row.get(3)?,
-        })
-    })
+    let pcs = stmt
+        .query_map([race_id], pc_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(pcs)
+}
+
+fn query_pc(conn: &Connection, id: i64) -> Result<PC, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM pcs WHERE id = ?1", PC_COLUMNS),
+        [id],
+        pc_from_row,
+    )
     .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn create_pc(db: State<Database>, race_id: i64) -> Result<PC, String> {
+pub fn get_pcs_by_race(db: State<Database>, race_id: i64) -> Result<Vec<PC>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    query_pcs(&conn, race_id)
+}
+
+#[tauri::command]
+pub fn get_pc(db: State<Database>, id: i64) -> Result<PC, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    query_pc(&conn, id)
+}
+
+#[tauri::command]
+pub fn create_pc(db: State<Database>, race_id: i64) -> Result<PC, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&tx, race_id)?;
 
     // Get the next pc_number for this race
-    let next_number: i32 = conn
+    let next_number: i32 = tx
         .query_row(
-            "SELECT COALESCE(MAX(pc_number), 0) + 1 FROM pcs WHERE race_id = ?1",
+            "SELECT COALESCE(MAX(pc_number), 0) + 1 FROM pcs WHERE race_id = ?1 AND deleted_at IS NULL",
             [race_id],
             |row| row.get(0),
         )
         .map_err(|e| e.to_string())?;
 
-    conn.execute(
+    tx.execute(
         "INSERT INTO pcs (race_id, pc_number) VALUES (?1, ?2)",
         [race_id, next_number as i64],
     )
     .map_err(|e| e.to_string())?;
 
-    let id = conn.last_insert_rowid();
-    let mut stmt = conn
-        .prepare("SELECT id, race_id, pc_number, created_at FROM pcs WHERE id = ?1")
-        .map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid();
+    record_pc_revision(&tx, id, "create_pc")?;
+    let mut before = EditState::default();
+    before.created_pc(id);
+    record_edit(&tx, "create_pc", before)?;
+    let pc = query_pc(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pc)
+}
 
-    stmt.query_row([id], |row| {
-        Ok(PC {
-            id: row.get(0)?,
-            race_id: row.get(1)?,
-            pc_number: row.get(2)?,
-            created_at: row.get(3)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+#[tauri::command]
+pub fn update_pc(db: State<Database>, request: UpdatePCRequest) -> Result<PC, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_pc_unlocked(&tx, request.id)?;
+    let before = capture_edit(&tx, &[], &[request.id], false)?;
+    tx.execute(
+        "UPDATE pcs
+         SET name = ?1, pc_type = ?2, start_location = ?3, scheduled_start_centiseconds = ?4,
+             target_time_centiseconds = ?5, total_distance_meters = ?6, notes = ?7
+         WHERE id = ?8",
+        rusqlite::params![
+            request.name,
+            request.pc_type,
+            request.start_location,
+            request.scheduled_start_centiseconds,
+            request.target_time_centiseconds,
+            request.total_distance_meters,
+            request.notes,
+            request.id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    record_pc_revision(&tx, request.id, "update_pc")?;
+    record_edit(&tx, "update_pc", before)?;
+    let pc = query_pc(&tx, request.id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pc)
 }
 
 #[tauri::command]
-pub fn delete_pc(db: State<Database>, id: i64) -> Result<(), String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    // Foreign key cascade will delete associated references
-    conn.execute("DELETE FROM pcs WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+pub fn delete_pc(db: State<Database>, id: i64) -> Result<(), CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_pc_unlocked(&tx, id)?;
+    let before = capture_edit(&tx, &[], &[id], false)?;
+    // The PC goes to the trash with its references
+    tx.execute(
+        "UPDATE pcs SET deleted_at = datetime('now') WHERE id = ?1 AND deleted_at IS NULL",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+    record_edit(&tx, "delete_pc", before)?;
+    tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -188,35 +453,17 @@ pub fn get_next_pc(db: State<Database>, pc_id: i64) -> Result<Option<PC>, String
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
 
     // First get the current PC to find race_id and pc_number
-    let current: PC = conn
-        .query_row(
-            "SELECT id, race_id, pc_number, created_at FROM pcs WHERE id = ?1",
-            [pc_id],
-            |row| {
-                Ok(PC {
-                    id: row.get(0)?,
-                    race_id: row.get(1)?,
-                    pc_number: row.get(2)?,
-                    created_at: row.get(3)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?;
+    let current = query_pc(&conn, pc_id)?;
 
     // Find the next PC by number
-    let mut stmt = conn
-        .prepare("SELECT id, race_id, pc_number, created_at FROM pcs WHERE race_id = ?1 AND pc_number > ?2 ORDER BY pc_number ASC LIMIT 1")
-        .map_err(|e| e.to_string())?;
-
-    let result = stmt
-        .query_row([current.race_id, current.pc_number as i64], |row| {
-            Ok(PC {
-                id: row.get(0)?,
-                race_id: row.get(1)?,
-                pc_number: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        });
+    let result = conn.query_row(
+        &format!(
+            "SELECT {} FROM pcs WHERE race_id = ?1 AND pc_number > ?2 AND deleted_at IS NULL ORDER BY pc_number ASC LIMIT 1",
+            PC_COLUMNS
+        ),
+        [current.race_id, current.pc_number as i64],
+        pc_from_row,
+    );
 
     match result {
         Ok(pc) => Ok(Some(pc)),
@@ -226,7 +473,7 @@ pub fn get_next_pc(db: State<Database>, pc_id: i64) -> Result<Option<PC>, String
 }
 
 #[tauri::command]
-pub fn create_next_pc(db: State<Database>, current_pc_id: i64) -> Result<PC, String> {
+pub fn create_next_pc(db: State<Database>, current_pc_id: i64) -> Result<PC, CommandError> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
 
     // Get the current PC's race_id
@@ -235,11 +482,12 @@ pub fn create_next_pc(db: State<Database>, current_pc_id: i64) -> Result<PC, Str
             row.get(0)
         })
         .map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&conn, race_id)?;
 
     // Get the next pc_number for this race
     let next_number: i32 = conn
         .query_row(
-            "SELECT COALESCE(MAX(pc_number), 0) + 1 FROM pcs WHERE race_id = ?1",
+            "SELECT COALESCE(MAX(pc_number), 0) + 1 FROM pcs WHERE race_id = ?1 AND deleted_at IS NULL",
             [race_id],
             |row| row.get(0),
         )
@@ -252,52 +500,147 @@ pub fn create_next_pc(db: State<Database>, current_pc_id: i64) -> Result<PC, Str
     .map_err(|e| e.to_string())?;
 
     let id = conn.last_insert_rowid();
-    let mut stmt = conn
-        .prepare("SELECT id, race_id, pc_number, created_at FROM pcs WHERE id = ?1")
+    record_pc_revision(&conn, id, "create_next_pc")?;
+    let mut before = EditState::default();
+    before.created_pc(id);
+    record_edit(&conn, "create_next_pc", before)?;
+    Ok(query_pc(&conn, id)?)
+}
+
+// ==================== PC NUMBERING COMMANDS ====================
+
+// Assigns pc_number 1..n following the given id order. Numbers go through negative
+// values first so the UNIQUE(race_id, pc_number) constraint holds at every step.
+fn write_pc_numbers(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    for (index, id) in ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE pcs SET pc_number = ?1 WHERE id = ?2",
+            [-(index as i64 + 1), *id],
+        )
         .map_err(|e| e.to_string())?;
+    }
+    for id in ids {
+        conn.execute("UPDATE pcs SET pc_number = -pc_number WHERE id = ?1", [*id])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
 
-    stmt.query_row([id], |row| {
-        Ok(PC {
-            id: row.get(0)?,
-            race_id: row.get(1)?,
-            pc_number: row.get(2)?,
-            created_at: row.get(3)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+// Creates a PC with the given number, shifting that PC and every later one up by one
+#[tauri::command]
+pub fn insert_pc_at(db: State<Database>, race_id: i64, pc_number: i32) -> Result<PC, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&tx, race_id)?;
+    let pc_number = pc_number.max(1);
+    let pc_ids: Vec<i64> = query_pcs(&tx, race_id)?.iter().map(|pc| pc.id).collect();
+    let mut before = capture_edit(&tx, &[], &pc_ids, false)?;
+
+    tx.execute(
+        "UPDATE pcs SET pc_number = -(pc_number + 1) WHERE race_id = ?1 AND pc_number >= ?2 AND deleted_at IS NULL",
+        [race_id, pc_number as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE pcs SET pc_number = -pc_number WHERE race_id = ?1 AND pc_number < 0 AND deleted_at IS NULL",
+        [race_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    tx.execute(
+        "INSERT INTO pcs (race_id, pc_number) VALUES (?1, ?2)",
+        [race_id, pc_number as i64],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let pc = query_pc(&tx, tx.last_insert_rowid())?;
+    record_pc_revision(&tx, pc.id, "insert_pc_at")?;
+    before.created_pc(pc.id);
+    record_edit(&tx, "insert_pc_at", before)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pc)
 }
 
-// ==================== REFERENCE COMMANDS ====================
+// Closes the holes left by deleted PCs, keeping their relative order
+#[tauri::command]
+pub fn renumber_pcs(db: State<Database>, race_id: i64) -> Result<Vec<PC>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&tx, race_id)?;
+
+    let ids: Vec<i64> = query_pcs(&tx, race_id)?.iter().map(|pc| pc.id).collect();
+    let before = capture_edit(&tx, &[], &ids, false)?;
+    write_pc_numbers(&tx, &ids)?;
+    record_edit(&tx, "renumber_pcs", before)?;
+
+    let pcs = query_pcs(&tx, race_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pcs)
+}
 
+// Moves a PC so it becomes number `new_number`; the race is renumbered 1..n
 #[tauri::command]
-pub fn get_references_by_pc(db: State<Database>, pc_id: i64) -> Result<Vec<ReferenceEntry>, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+pub fn move_pc(db: State<Database>, id: i64, new_number: i32) -> Result<Vec<PC>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let race_id = query_pc(&tx, id)?.race_id;
+    ensure_race_unlocked(&tx, race_id)?;
+    let mut ids: Vec<i64> = query_pcs(&tx, race_id)?.iter().map(|pc| pc.id).collect();
+    let current = ids
+        .iter()
+        .position(|&pc_id| pc_id == id)
+        .ok_or_else(|| format!("PC {} not found", id))?;
+
+    let before = capture_edit(&tx, &[], &ids, false)?;
+    let target = ((new_number.max(1) - 1) as usize).min(ids.len() - 1);
+    let moved = ids.remove(current);
+    ids.insert(target, moved);
+    write_pc_numbers(&tx, &ids)?;
+    record_edit(&tx, "move_pc", before)?;
+
+    let pcs = query_pcs(&tx, race_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pcs)
+}
+
+// ==================== REFERENCE COMMANDS ====================
+
+const REFERENCE_COLUMNS: &str = "id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters,
+    note, landmark, EXISTS(SELECT 1 FROM reference_tulips WHERE reference_id = reference_entries.id), created_at";
+
+fn reference_from_row(row: &rusqlite::Row) -> rusqlite::Result<ReferenceEntry> {
+    Ok(ReferenceEntry {
+        id: row.get(0)?,
+        pc_id: row.get(1)?,
+        hours: row.get(2)?,
+        minutes: row.get(3)?,
+        seconds: row.get(4)?,
+        centiseconds: row.get(5)?,
+        event_type: row.get(6)?,
+        speed: row.get(7)?,
+        extra_value: row.get(8)?,
+        is_control_zone: row.get::<_, i32>(9)? != 0,
+        order_index: row.get(10)?,
+        defined_by: row.get(11)?,
+        distance_meters: row.get(12)?,
+        note: row.get(13)?,
+        landmark: row.get(14)?,
+        has_tulip: row.get(15)?,
+        created_at: row.get(16)?,
+    })
+}
+
+fn query_references(conn: &Connection, pc_id: i64) -> Result<Vec<ReferenceEntry>, String> {
     let mut stmt = conn
-        .prepare(
-            "SELECT id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, created_at
-             FROM reference_entries
-             WHERE pc_id = ?1
-             ORDER BY order_index ASC",
-        )
+        .prepare(&format!(
+            "SELECT {} FROM reference_entries WHERE pc_id = ?1 ORDER BY order_index ASC",
+            REFERENCE_COLUMNS
+        ))
         .map_err(|e| e.to_string())?;
 
     let refs = stmt
-        .query_map([pc_id], |row| {
-            Ok(ReferenceEntry {
-                id: row.get(0)?,
-                pc_id: row.get(1)?,
-                hours: row.get(2)?,
-                minutes: row.get(3)?,
-                seconds: row.get(4)?,
-                centiseconds: row.get(5)?,
-                event_type: row.get(6)?,
-                speed: row.get(7)?,
-                extra_value: row.get(8)?,
-                is_control_zone: row.get::<_, i32>(9)? != 0,
-                order_index: row.get(10)?,
-                created_at: row.get(11)?,
-            })
-        })
+        .query_map([pc_id], reference_from_row)
         .map_err(|e| e.to_string())?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| e.to_string())?;
@@ -305,22 +648,96 @@ pub fn get_references_by_pc(db: State<Database>, pc_id: i64) -> Result<Vec<Refer
     Ok(refs)
 }
 
+fn query_reference(conn: &Connection, id: i64) -> Result<ReferenceEntry, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM reference_entries WHERE id = ?1", REFERENCE_COLUMNS),
+        [id],
+        reference_from_row,
+    )
+    .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
-pub fn create_reference(db: State<Database>, request: CreateReferenceRequest) -> Result<ReferenceEntry, String> {
+pub fn get_references_by_pc(db: State<Database>, pc_id: i64) -> Result<Vec<ReferenceEntry>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    query_references(&conn, pc_id)
+}
 
-    // Get the next order_index for this PC
-    let next_index: i32 = conn
-        .query_row(
-            "SELECT COALESCE(MAX(order_index), -1) + 1 FROM reference_entries WHERE pc_id = ?1",
-            [request.pc_id],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
+// Checks how a reference is defined, returning the stored `defined_by` value
+fn check_definition(defined_by: Option<&str>, distance_meters: Option<f64>) -> Result<&'static str, String> {
+    match defined_by.unwrap_or("time") {
+        "time" => Ok("time"),
+        "distance" => match distance_meters {
+            Some(distance) if distance >= 0.0 => Ok("distance"),
+            _ => Err("A reference defined by distance needs a distance from the start".to_string()),
+        },
+        other => Err(format!("Unknown reference definition: {}", other)),
+    }
+}
+
+// Recomputes the passage time of references defined by distance and the
+// distance of references defined by time, after anything before them in the
+// PC changed. Typed values are never written back, so a time out of range
+// stays as entered for validation to report.
+fn recompute_pc_route(conn: &Connection, pc_id: i64) -> Result<(), String> {
+    let refs = query_references(conn, pc_id)?;
+    let points = route::compile(&refs);
+
+    for (mut reference, point) in refs.into_iter().zip(points) {
+        if reference.defined_by == "distance" {
+            let time = point.time_centiseconds.rem_euclid(24 * 360000);
+            if reference.time_centiseconds() == time {
+                continue;
+            }
+            reference.set_time_centiseconds(time);
+            conn.execute(
+                "UPDATE reference_entries SET hours = ?1, minutes = ?2, seconds = ?3, centiseconds = ?4 WHERE id = ?5",
+                rusqlite::params![reference.hours, reference.minutes, reference.seconds, reference.centiseconds, reference.id],
+            )
+            .map_err(|e| e.to_string())?;
+        } else if reference.distance_meters != Some(point.distance_meters) {
+            conn.execute(
+                "UPDATE reference_entries SET distance_meters = ?1 WHERE id = ?2",
+                rusqlite::params![point.distance_meters, reference.id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Fills in the route of PCs whose references have no distance yet (data
+// created before distances were computed)
+pub fn recompute_stale_routes(db: &Database) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let pc_ids = {
+        let mut stmt = tx
+            .prepare("SELECT DISTINCT pc_id FROM reference_entries WHERE distance_meters IS NULL")
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map([], |row| row.get::<_, i64>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        ids
+    };
+
+    for pc_id in pc_ids {
+        recompute_pc_route(&tx, pc_id)?;
+    }
+
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn insert_reference(conn: &Connection, request: &CreateReferenceRequest, order_index: i32) -> Result<i64, String> {
+    let defined_by = check_definition(request.defined_by.as_deref(), request.distance_meters)?;
 
     conn.execute(
-        "INSERT INTO reference_entries (pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, order_index)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO reference_entries (pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, defined_by, distance_meters, note, landmark, order_index)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
         rusqlite::params![
             request.pc_id,
             request.hours,
@@ -330,46 +747,58 @@ pub fn create_reference(db: State<Database>, request: CreateReferenceRequest) ->
             request.event_type,
             request.speed,
             request.extra_value,
-            next_index
+            defined_by,
+            request.distance_meters,
+            request.note,
+            request.landmark,
+            order_index
         ],
     )
     .map_err(|e| e.to_string())?;
 
-    let id = conn.last_insert_rowid();
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, created_at
-             FROM reference_entries WHERE id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    stmt.query_row([id], |row| {
-        Ok(ReferenceEntry {
-            id: row.get(0)?,
-            pc_id: row.get(1)?,
-            hours: row.get(2)?,
-            minutes: row.get(3)?,
-            seconds: row.get(4)?,
-            centiseconds: row.get(5)?,
-            event_type: row.get(6)?,
-            speed: row.get(7)?,
-            extra_value: row.get(8)?,
-            is_control_zone: row.get::<_, i32>(9)? != 0,
-            order_index: row.get(10)?,
-            created_at: row.get(11)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+    Ok(conn.last_insert_rowid())
 }
 
 #[tauri::command]
-pub fn update_reference(db: State<Database>, request: UpdateReferenceRequest) -> Result<ReferenceEntry, String> {
-    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+pub fn create_reference(db: State<Database>, request: CreateReferenceRequest) -> Result<ReferenceEntry, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_pc_unlocked(&tx, request.pc_id)?;
+    let before = capture_edit(&tx, &[], &[request.pc_id], true)?;
 
-    conn.execute(
+    // Get the next order_index for this PC
+    let next_index: i32 = tx
+        .query_row(
+            "SELECT COALESCE(MAX(order_index), -1) + 1 FROM reference_entries WHERE pc_id = ?1",
+            [request.pc_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let id = insert_reference(&tx, &request, next_index)?;
+    recompute_pc_route(&tx, request.pc_id)?;
+    record_pc_revision(&tx, request.pc_id, "create_reference")?;
+    record_edit(&tx, "create_reference", before)?;
+
+    let reference = query_reference(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(reference)
+}
+
+#[tauri::command]
+pub fn update_reference(db: State<Database>, request: UpdateReferenceRequest) -> Result<ReferenceEntry, CommandError> {
+    let defined_by = check_definition(request.defined_by.as_deref(), request.distance_meters)?;
+
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, request.id)?;
+    let before = capture_edit(&tx, &[], &[query_reference(&tx, request.id)?.pc_id], true)?;
+
+    tx.execute(
         "UPDATE reference_entries
-         SET hours = ?1, minutes = ?2, seconds = ?3, centiseconds = ?4, event_type = ?5, speed = ?6, extra_value = ?7
-         WHERE id = ?8",
+         SET hours = ?1, minutes = ?2, seconds = ?3, centiseconds = ?4, event_type = ?5, speed = ?6, extra_value = ?7,
+             defined_by = ?8, distance_meters = ?9, note = ?10, landmark = ?11
+         WHERE id = ?12",
         rusqlite::params![
             request.hours,
             request.minutes,
@@ -378,79 +807,2954 @@ pub fn update_reference(db: State<Database>, request: UpdateReferenceRequest) ->
             request.event_type,
             request.speed,
             request.extra_value,
+            defined_by,
+            request.distance_meters,
+            request.note,
+            request.landmark,
             request.id
         ],
     )
     .map_err(|e| e.to_string())?;
 
+    let reference = query_reference(&tx, request.id)?;
+    // A new speed on a speed change holds until the next one
+    if reference.event_type == "LAR" || reference.is_speed_change() {
+        write_segment_speed(&tx, request.id, request.speed)?;
+    }
+    recompute_pc_route(&tx, reference.pc_id)?;
+    record_pc_revision(&tx, reference.pc_id, "update_reference")?;
+    record_edit(&tx, "update_reference", before)?;
+
+    let reference = query_reference(&tx, request.id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(reference)
+}
+
+// Sets the speed of a LAR/CVT/CVD/CVR and of every following reference
+// up to (not including) the next speed change. Returns the PC id.
+fn write_segment_speed(conn: &Connection, id: i64, speed: i64) -> Result<i64, String> {
+    let start = query_reference(conn, id)?;
+    if start.event_type != "LAR" && !start.is_speed_change() {
+        return Err(format!(
+            "Reference {} is a {}, speed can only change on LAR, CVT, CVD or CVR",
+            id, start.event_type
+        ));
+    }
+
+    let refs = query_references(conn, start.pc_id)?;
+    let segment = refs
+        .iter()
+        .skip_while(|r| r.id != id)
+        .enumerate()
+        .take_while(|(i, r)| *i == 0 || !r.is_speed_change())
+        .map(|(_, r)| r.id);
+
+    for ref_id in segment {
+        conn.execute(
+            "UPDATE reference_entries SET speed = ?1 WHERE id = ?2",
+            rusqlite::params![speed, ref_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    Ok(start.pc_id)
+}
+
+// Same as `write_segment_speed`, in a single transaction
+#[tauri::command]
+pub fn set_segment_speed(db: State<Database>, id: i64, speed: i64) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, id)?;
+    let before = capture_edit(&tx, &[], &[query_reference(&tx, id)?.pc_id], true)?;
+
+    let pc_id = write_segment_speed(&tx, id, speed)?;
+    recompute_pc_route(&tx, pc_id)?;
+    record_pc_revision(&tx, pc_id, "set_segment_speed")?;
+    record_edit(&tx, "set_segment_speed", before)?;
+
+    let refs = query_references(&tx, pc_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(refs)
+}
+
+#[tauri::command]
+pub fn delete_reference(db: State<Database>, id: i64) -> Result<(), CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, id)?;
+
+    let pc_id = query_reference(&tx, id)?.pc_id;
+    let before = capture_edit(&tx, &[], &[pc_id], true)?;
+    tx.execute("DELETE FROM reference_entries WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+
+    // Close the gap left in the order
+    let ids = query_reference_ids(&tx, pc_id)?;
+    write_reference_order(&tx, &ids)?;
+    recompute_pc_route(&tx, pc_id)?;
+    record_pc_revision(&tx, pc_id, "delete_reference")?;
+    record_edit(&tx, "delete_reference", before)?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn toggle_control_zone(db: State<Database>, id: i64) -> Result<ReferenceEntry, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, id)?;
+    let before = capture_edit(&tx, &[], &[query_reference(&tx, id)?.pc_id], true)?;
+
+    tx.execute(
+        "UPDATE reference_entries SET is_control_zone = NOT is_control_zone WHERE id = ?1",
+        [id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let reference = query_reference(&tx, id)?;
+    record_pc_revision(&tx, reference.pc_id, "toggle_control_zone")?;
+    record_edit(&tx, "toggle_control_zone", before)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(reference)
+}
+
+// ==================== TULIP COMMANDS ====================
+
+const TULIP_COLUMNS: &str = "id, reference_id, mime_type, data, description, created_at";
+
+fn tulip_from_row(row: &rusqlite::Row) -> rusqlite::Result<ReferenceTulip> {
+    Ok(ReferenceTulip {
+        id: row.get(0)?,
+        reference_id: row.get(1)?,
+        mime_type: row.get(2)?,
+        data: row.get(3)?,
+        description: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+fn query_pc_tulips(conn: &Connection, pc_id: i64) -> Result<Vec<ReferenceTulip>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM reference_tulips
+             WHERE reference_id IN (SELECT id FROM reference_entries WHERE pc_id = ?1)
+             ORDER BY id ASC",
+            TULIP_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let tulips = stmt
+        .query_map([pc_id], tulip_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(tulips)
+}
+
+fn query_reference_tulip(conn: &Connection, reference_id: i64) -> Result<Option<ReferenceTulip>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM reference_tulips WHERE reference_id = ?1", TULIP_COLUMNS),
+        [reference_id],
+        tulip_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_reference_tulip(db: State<Database>, reference_id: i64) -> Result<Option<ReferenceTulip>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    query_reference_tulip(&conn, reference_id)
+}
+
+// Attaches a tulip image to a reference, replacing the previous one
+#[tauri::command]
+pub fn set_reference_tulip(
+    db: State<Database>,
+    reference_id: i64,
+    mime_type: String,
+    data: Vec<u8>,
+    description: Option<String>,
+) -> Result<ReferenceTulip, CommandError> {
+    if !mime_type.starts_with("image/") {
+        return Err(format!("Tulip must be an image, got {}", mime_type).into());
+    }
+    if data.is_empty() {
+        return Err("Tulip image is empty".to_string().into());
+    }
+
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, reference_id)?;
+    let pc_id = query_reference(&tx, reference_id)?.pc_id;
+    let before = capture_edit(&tx, &[], &[pc_id], true)?;
+
+    tx.execute(
+        "INSERT INTO reference_tulips (reference_id, mime_type, data, description)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(reference_id) DO UPDATE
+         SET mime_type = excluded.mime_type, data = excluded.data, description = excluded.description,
+             created_at = datetime('now')",
+        rusqlite::params![reference_id, mime_type, data, description],
+    )
+    .map_err(|e| e.to_string())?;
+    record_pc_revision(&tx, pc_id, "set_reference_tulip")?;
+    record_edit(&tx, "set_reference_tulip", before)?;
+
+    let tulip = query_reference_tulip(&tx, reference_id)?
+        .ok_or_else(|| format!("Reference {} has no tulip", reference_id))?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(tulip)
+}
+
+#[tauri::command]
+pub fn delete_reference_tulip(db: State<Database>, reference_id: i64) -> Result<(), CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, reference_id)?;
+    let pc_id = query_reference(&tx, reference_id)?.pc_id;
+    let before = capture_edit(&tx, &[], &[pc_id], true)?;
+
+    tx.execute("DELETE FROM reference_tulips WHERE reference_id = ?1", [reference_id])
+        .map_err(|e| e.to_string())?;
+    record_pc_revision(&tx, pc_id, "delete_reference_tulip")?;
+    record_edit(&tx, "delete_reference_tulip", before)?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ==================== REFERENCE ORDER COMMANDS ====================
+
+fn query_reference_ids(conn: &Connection, pc_id: i64) -> Result<Vec<i64>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id FROM reference_entries WHERE pc_id = ?1 ORDER BY order_index ASC, id ASC")
+        .map_err(|e| e.to_string())?;
+
+    let ids = stmt
+        .query_map([pc_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(ids)
+}
+
+// Rewrites order_index as 0..n following the given id order
+fn write_reference_order(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("UPDATE reference_entries SET order_index = ?1 WHERE id = ?2 AND order_index != ?1")
+        .map_err(|e| e.to_string())?;
+
+    for (index, id) in ids.iter().enumerate() {
+        stmt.execute(rusqlite::params![index as i32, id])
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn reference_position(conn: &Connection, id: i64) -> Result<i32, String> {
+    let pc_id = query_reference(conn, id)?.pc_id;
+    let ids = query_reference_ids(conn, pc_id)?;
+    Ok(ids.iter().position(|&ref_id| ref_id == id).unwrap_or(0) as i32)
+}
+
+fn move_reference_to(conn: &mut Connection, id: i64, new_index: i32) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_reference_unlocked(&tx, id)?;
+
+    let pc_id = query_reference(&tx, id)?.pc_id;
+    let before = capture_edit(&tx, &[], &[pc_id], true)?;
+    let mut ids = query_reference_ids(&tx, pc_id)?;
+    let current = ids
+        .iter()
+        .position(|&ref_id| ref_id == id)
+        .ok_or_else(|| format!("Reference {} not found", id))?;
+
+    let target = (new_index.max(0) as usize).min(ids.len() - 1);
+    let moved = ids.remove(current);
+    ids.insert(target, moved);
+    write_reference_order(&tx, &ids)?;
+    recompute_pc_route(&tx, pc_id)?;
+    record_pc_revision(&tx, pc_id, "move_reference")?;
+    record_edit(&tx, "move_reference", before)?;
+
+    let refs = query_references(&tx, pc_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(refs)
+}
+
+#[tauri::command]
+pub fn insert_reference_at(
+    db: State<Database>,
+    request: CreateReferenceRequest,
+    position: i32,
+) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_pc_unlocked(&tx, request.pc_id)?;
+    let before = capture_edit(&tx, &[], &[request.pc_id], true)?;
+
+    let mut ids = query_reference_ids(&tx, request.pc_id)?;
+    let target = (position.max(0) as usize).min(ids.len());
+    let id = insert_reference(&tx, &request, target as i32)?;
+    ids.insert(target, id);
+    write_reference_order(&tx, &ids)?;
+    recompute_pc_route(&tx, request.pc_id)?;
+    record_pc_revision(&tx, request.pc_id, "insert_reference_at")?;
+    record_edit(&tx, "insert_reference_at", before)?;
+
+    let refs = query_references(&tx, request.pc_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(refs)
+}
+
+#[tauri::command]
+pub fn move_reference(db: State<Database>, id: i64, new_index: i32) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    move_reference_to(&mut conn, id, new_index)
+}
+
+#[tauri::command]
+pub fn move_reference_up(db: State<Database>, id: i64) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let index = reference_position(&conn, id)?;
+    move_reference_to(&mut conn, id, index - 1)
+}
+
+#[tauri::command]
+pub fn move_reference_down(db: State<Database>, id: i64) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let index = reference_position(&conn, id)?;
+    move_reference_to(&mut conn, id, index + 1)
+}
+
+#[tauri::command]
+pub fn compact_reference_order(db: State<Database>, pc_id: i64) -> Result<Vec<ReferenceEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    ensure_pc_unlocked(&tx, pc_id)?;
+
+    let before = capture_edit(&tx, &[], &[pc_id], true)?;
+    let ids = query_reference_ids(&tx, pc_id)?;
+    write_reference_order(&tx, &ids)?;
+    record_pc_revision(&tx, pc_id, "compact_reference_order")?;
+    record_edit(&tx, "compact_reference_order", before)?;
+
+    let refs = query_references(&tx, pc_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(refs)
+}
+
+// ==================== DUPLICATE COMMANDS ====================
+
+// Copies a PC row and its references into `race_id` as number `pc_number`
+fn copy_pc(conn: &Connection, source_id: i64, race_id: i64, pc_number: i32, options: &DuplicateOptions) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO pcs (race_id, pc_number, name, pc_type, start_location, scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes)
+         SELECT ?1, ?2, name, pc_type, start_location, scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes
+         FROM pcs WHERE id = ?3",
+        rusqlite::params![race_id, pc_number, source_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let new_pc_id = conn.last_insert_rowid();
+
+    if let Some(offset) = options.time_offset_centiseconds {
+        conn.execute(
+            "UPDATE pcs SET scheduled_start_centiseconds = ((scheduled_start_centiseconds + ?1) % 8640000 + 8640000) % 8640000
+             WHERE id = ?2",
+            [offset, new_pc_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    copy_pc_references(conn, source_id, new_pc_id, options)?;
+    Ok(new_pc_id)
+}
+
+// Copies every reference of one PC into another, applying the time offset and
+// speed scaling. Scaling keeps every reference at its km mark on the road and
+// works the passage times out again from the new speeds.
+fn copy_pc_references(conn: &Connection, from_pc_id: i64, to_pc_id: i64, options: &DuplicateOptions) -> Result<(), String> {
+    if let Some(scale) = options.speed_scale.filter(|scale| !(scale.is_finite() && *scale > 0.0)) {
+        return Err(format!("Speed scale must be a positive number, got {}", scale));
+    }
+
+    let source = query_references(conn, from_pc_id)?;
+    for reference in &source {
+        conn.execute(
+            "INSERT INTO reference_entries (pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark)
+             SELECT ?1, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark
+             FROM reference_entries WHERE id = ?2",
+            [to_pc_id, reference.id],
+        )
+        .map_err(|e| e.to_string())?;
+        let copy_id = conn.last_insert_rowid();
+
+        // Tulips follow the reference they were copied with
+        conn.execute(
+            "INSERT INTO reference_tulips (reference_id, mime_type, data, description)
+             SELECT ?1, mime_type, data, description FROM reference_tulips WHERE reference_id = ?2",
+            [copy_id, reference.id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let offset = options.time_offset_centiseconds.unwrap_or(0);
+    let scale = options.speed_scale.unwrap_or(1.0);
+    if offset == 0 && scale == 1.0 {
+        return Ok(());
+    }
+
+    let mut copies = query_references(conn, to_pc_id)?;
+    let points = if scale == 1.0 {
+        route::compile(&copies)
+    } else {
+        // Every reference is placed by its distance, so the times follow the scaled speeds
+        let distances: Vec<f64> = route::compile(&copies).iter().map(|point| point.distance_meters).collect();
+        let placed: Vec<ReferenceEntry> = copies
+            .iter()
+            .zip(distances)
+            .map(|(reference, distance)| ReferenceEntry {
+                speed: (reference.speed as f64 * scale).round() as i64,
+                defined_by: "distance".to_string(),
+                distance_meters: Some(distance),
+                ..reference.clone()
+            })
+            .collect();
+        route::compile(&placed)
+    };
+
+    for (reference, point) in copies.iter_mut().zip(points) {
+        reference.set_time_centiseconds((point.time_centiseconds + offset).rem_euclid(24 * 360000));
+
+        conn.execute(
+            "UPDATE reference_entries
+             SET hours = ?1, minutes = ?2, seconds = ?3, centiseconds = ?4, speed = ?5, distance_meters = ?6
+             WHERE id = ?7",
+            rusqlite::params![
+                reference.hours,
+                reference.minutes,
+                reference.seconds,
+                reference.centiseconds,
+                point.speed,
+                point.distance_meters,
+                reference.id
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+// Copies a race with its PCs, references and tulips under a new name. With a
+// speed scale the references keep their distances and get new passage times.
+#[tauri::command]
+pub fn duplicate_race(
+    db: State<Database>,
+    id: i64,
+    name: Option<String>,
+    options: Option<DuplicateOptions>,
+) -> Result<Race, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let options = options.unwrap_or_default();
+
+    let source = query_race(&tx, id)?;
+    let name = name.unwrap_or_else(|| format!("{} (copia)", source.name));
+    tx.execute(
+        "INSERT INTO races (name, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes)
+         SELECT ?1, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes
+         FROM races WHERE id = ?2",
+        rusqlite::params![name, id],
+    )
+    .map_err(|e| e.to_string())?;
+    let new_race_id = tx.last_insert_rowid();
+
+    record_race_revision(&tx, new_race_id, "duplicate_race")?;
+    for pc in query_pcs(&tx, id)? {
+        let new_pc_id = copy_pc(&tx, pc.id, new_race_id, pc.pc_number, &options)?;
+        record_pc_revision(&tx, new_pc_id, "duplicate_race")?;
+    }
+    let mut before = EditState::default();
+    before.created_race(new_race_id, true);
+    record_edit(&tx, "duplicate_race", before)?;
+
+    let race = query_race(&tx, new_race_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(race)
+}
+
+// Copies a PC with all its references to the end of its race, or of
+// `target_race_id`. As with races, scaling keeps the distances and moves the times.
+#[tauri::command]
+pub fn duplicate_pc(
+    db: State<Database>,
+    id: i64,
+    target_race_id: Option<i64>,
+    options: Option<DuplicateOptions>,
+) -> Result<PC, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let options = options.unwrap_or_default();
+
+    let source = query_pc(&tx, id)?;
+    let race_id = target_race_id.unwrap_or(source.race_id);
+    ensure_race_unlocked(&tx, race_id)?;
+
+    let next_number: i32 = tx
+        .query_row(
+            "SELECT COALESCE(MAX(pc_number), 0) + 1 FROM pcs WHERE race_id = ?1 AND deleted_at IS NULL",
+            [race_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let new_pc_id = copy_pc(&tx, id, race_id, next_number, &options)?;
+    record_pc_revision(&tx, new_pc_id, "duplicate_pc")?;
+    let mut before = EditState::default();
+    before.created_pc(new_pc_id);
+    record_edit(&tx, "duplicate_pc", before)?;
+
+    let pc = query_pc(&tx, new_pc_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pc)
+}
+
+// ==================== ROUTE COMMANDS ====================
+
+#[tauri::command]
+pub fn get_route_intervals(db: State<Database>, pc_id: i64) -> Result<Vec<RouteInterval>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let refs = query_references(&conn, pc_id)?;
+    Ok(route::intervals(&refs))
+}
+
+// ==================== MARCH TABLE COMMANDS ====================
+
+#[tauri::command]
+pub fn get_march_table(db: State<Database>, pc_id: i64, options: MarchTableOptions) -> Result<Vec<MarchRow>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let refs = query_references(&conn, pc_id)?;
+    march_table::build(&refs, &options)
+}
+
+#[tauri::command]
+pub fn export_march_table_csv(
+    db: State<Database>,
+    pc_id: i64,
+    options: MarchTableOptions,
+    path: String,
+) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let refs = query_references(&conn, pc_id)?;
+    let rows = march_table::build(&refs, &options)?;
+    march_table::write_csv(&rows, &path)
+}
+
+#[tauri::command]
+pub fn export_march_table_pdf(
+    db: State<Database>,
+    pc_id: i64,
+    options: MarchTableOptions,
+    path: String,
+) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let pc = query_pc(&conn, pc_id)?;
+    let race = query_race(&conn, pc.race_id)?;
+    let refs = query_references(&conn, pc_id)?;
+    let rows = march_table::build(&refs, &options)?;
+
+    let mut title = format!("Tabla de marcha - {} - PC {}", race.name, pc.pc_number);
+    if let Some(name) = &pc.name {
+        title.push_str(&format!(" {}", name));
+    }
+    march_table::write_pdf(&title, &march_table::describe_step(&options), &rows, &path)
+}
+
+// ==================== VALIDATION COMMANDS ====================
+
+fn validate_pc_references(conn: &Connection, pc: &PC) -> Result<ValidationReport, String> {
+    let references = query_references(conn, pc.id)?;
+    let issues = validation::validate_references(pc, &references);
+    Ok(validation::build_report(pc.id, pc.pc_number, issues))
+}
+
+#[tauri::command]
+pub fn validate_pc(db: State<Database>, pc_id: i64) -> Result<ValidationReport, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let pc = query_pc(&conn, pc_id)?;
+    validate_pc_references(&conn, &pc)
+}
+
+#[tauri::command]
+pub fn validate_race(db: State<Database>, race_id: i64) -> Result<Vec<ValidationReport>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    query_pcs(&conn, race_id)?
+        .iter()
+        .map(|pc| validate_pc_references(&conn, pc))
+        .collect()
+}
+
+// ==================== CHECKSUM COMMANDS ====================
+
+fn pc_checksum(conn: &Connection, pc: &PC) -> Result<PCChecksum, String> {
+    let references = query_references(conn, pc.id)?;
+    Ok(checksum::pc_checksum(pc, &references))
+}
+
+fn race_checksum(conn: &Connection, race_id: i64) -> Result<RaceChecksum, String> {
+    query_race(conn, race_id)?;
+    let pcs = query_pcs(conn, race_id)?
+        .iter()
+        .map(|pc| pc_checksum(conn, pc))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(checksum::race_checksum(race_id, pcs))
+}
+
+#[tauri::command]
+pub fn get_race_checksum(db: State<Database>, race_id: i64) -> Result<RaceChecksum, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    race_checksum(&conn, race_id)
+}
+
+#[tauri::command]
+pub fn get_pc_checksum(db: State<Database>, pc_id: i64) -> Result<PCChecksum, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let pc = query_pc(&conn, pc_id)?;
+    pc_checksum(&conn, &pc)
+}
+
+// Per-PC breakdown against the fingerprints of another machine, keyed by PC
+// number, to find which PCs differ when the race fingerprints don't match
+#[tauri::command]
+pub fn compare_race_checksum(
+    db: State<Database>,
+    race_id: i64,
+    other: HashMap<i32, String>,
+) -> Result<Vec<PCChecksumComparison>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let local = race_checksum(&conn, race_id)?;
+
+    let mut numbers: Vec<i32> = local.pcs.iter().map(|pc| pc.pc_number).chain(other.keys().copied()).collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+
+    Ok(numbers
+        .into_iter()
+        .map(|pc_number| {
+            let local = local.pcs.iter().find(|pc| pc.pc_number == pc_number).map(|pc| pc.fingerprint.clone());
+            let other = other.get(&pc_number).map(|value| checksum::normalize_fingerprint(value));
+            PCChecksumComparison {
+                pc_number,
+                matches: local.is_some() && local == other,
+                local,
+                other,
+            }
+        })
+        .collect())
+}
+
+// ==================== ROADBOOK COMMANDS ====================
+
+// Exports the references of a race, or of a single PC of it, as a roadbook CSV
+#[tauri::command]
+pub fn export_roadbook_csv(db: State<Database>, race_id: i64, pc_id: Option<i64>, path: String) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    let pcs = query_pcs(&conn, race_id)?
+        .into_iter()
+        .filter(|pc| pc_id.map_or(true, |id| id == pc.id))
+        .map(|pc| Ok((pc.pc_number, query_references(&conn, pc.id)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    roadbook::write_csv(&pcs, &path)
+}
+
+// Imports roadbook rows into a race inside one transaction. PCs missing from
+// the race are created, existing ones get the references appended (or
+// replaced). A dry run, or a table with unreadable lines, is rolled back.
+fn import_roadbook(
+    conn: &mut Connection,
+    race_id: i64,
+    (headers, records): (Vec<String>, roadbook::Records),
+    mapping: HashMap<String, String>,
+    replace_existing: bool,
+    dry_run: bool,
+) -> Result<RoadbookImportReport, String> {
+    let columns = roadbook::resolve_mapping(&headers, &mapping)?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (line, record) in records {
+        match record.and_then(|record| roadbook::parse_record(line, &record, &columns)) {
+            Ok(row) => rows.push(row),
+            Err(message) => errors.push(RoadbookLineError { line, message }),
+        }
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    query_race(&tx, race_id)?;
+    let before = capture_race_edit(&tx, race_id)?;
+
+    // PCs in order of first appearance in the file
+    let mut pc_numbers: Vec<i32> = Vec::new();
+    for row in &rows {
+        if !pc_numbers.contains(&row.pc_number) {
+            pc_numbers.push(row.pc_number);
+        }
+    }
+
+    let mut pcs_created = Vec::new();
+    let mut references_created = 0;
+    let mut validation = Vec::new();
+
+    for pc_number in pc_numbers {
+        let existing: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM pcs WHERE race_id = ?1 AND pc_number = ?2 AND deleted_at IS NULL",
+                rusqlite::params![race_id, pc_number],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+
+        let pc_id = match existing {
+            Some(id) => {
+                if replace_existing {
+                    tx.execute("DELETE FROM reference_entries WHERE pc_id = ?1", [id])
+                        .map_err(|e| e.to_string())?;
+                }
+                id
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO pcs (race_id, pc_number) VALUES (?1, ?2)",
+                    rusqlite::params![race_id, pc_number],
+                )
+                .map_err(|e| e.to_string())?;
+                pcs_created.push(pc_number);
+                tx.last_insert_rowid()
+            }
+        };
+
+        let first_index: i32 = tx
+            .query_row(
+                "SELECT COALESCE(MAX(order_index), -1) + 1 FROM reference_entries WHERE pc_id = ?1",
+                [pc_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+
+        for (offset, row) in rows.iter().filter(|row| row.pc_number == pc_number).enumerate() {
+            let id = insert_reference(&tx, &row.to_request(pc_id), first_index + offset as i32)
+                .map_err(|e| format!("Line {}: {}", row.line, e))?;
+            if row.is_control_zone {
+                tx.execute("UPDATE reference_entries SET is_control_zone = 1 WHERE id = ?1", [id])
+                    .map_err(|e| e.to_string())?;
+            }
+            references_created += 1;
+        }
+
+        recompute_pc_route(&tx, pc_id)?;
+        record_pc_revision(&tx, pc_id, "import_roadbook")?;
+        validation.push(validate_pc_references(&tx, &query_pc(&tx, pc_id)?)?);
+    }
+
+    let applied = !dry_run && errors.is_empty();
+    if applied {
+        record_edit(&tx, "import_roadbook", before)?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+
+    Ok(RoadbookImportReport {
+        applied,
+        mapping: columns
+            .into_iter()
+            .map(|(field, index)| (field, headers[index].clone()))
+            .collect(),
+        rows_read: rows.len() + errors.len(),
+        references_created,
+        pcs_created,
+        errors,
+        validation,
+    })
+}
+
+// Dry run: reports what an import would create and its validation issues
+#[tauri::command]
+pub fn preview_roadbook_import(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    mapping: Option<HashMap<String, String>>,
+    replace_existing: Option<bool>,
+) -> Result<RoadbookImportReport, String> {
+    let table = roadbook::read_csv(&path)?;
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    import_roadbook(&mut conn, race_id, table, mapping.unwrap_or_default(), replace_existing.unwrap_or(false), true)
+}
+
+#[tauri::command]
+pub fn import_roadbook_csv(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    mapping: Option<HashMap<String, String>>,
+    replace_existing: Option<bool>,
+) -> Result<RoadbookImportReport, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&conn, race_id)?;
+    let table = roadbook::read_csv(&path)?;
+    backup::create(&conn, &db.backup_dir, backup::BEFORE_IMPORT)?;
+    Ok(import_roadbook(&mut conn, race_id, table, mapping.unwrap_or_default(), replace_existing.unwrap_or(false), false)?)
+}
+
+// ==================== XLSX IMPORT COMMANDS ====================
+
+#[tauri::command]
+pub fn get_xlsx_sheets(path: String) -> Result<Vec<XlsxSheetPreview>, String> {
+    xlsx::preview_sheets(&path, 15)
+}
+
+#[tauri::command]
+pub fn preview_xlsx_import(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    options: XlsxImportOptions,
+) -> Result<RoadbookImportReport, String> {
+    let table = xlsx::read_sheet(&path, options.sheet_name.as_deref(), options.header_row)?;
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    import_roadbook(&mut conn, race_id, table, options.mapping.unwrap_or_default(), options.replace_existing.unwrap_or(false), true)
+}
+
+#[tauri::command]
+pub fn import_roadbook_xlsx(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    options: XlsxImportOptions,
+) -> Result<RoadbookImportReport, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&conn, race_id)?;
+    let table = xlsx::read_sheet(&path, options.sheet_name.as_deref(), options.header_row)?;
+    backup::create(&conn, &db.backup_dir, backup::BEFORE_IMPORT)?;
+    Ok(import_roadbook(&mut conn, race_id, table, options.mapping.unwrap_or_default(), options.replace_existing.unwrap_or(false), false)?)
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, organizer, sheet_name, header_row, mapping, created_at";
+
+fn template_from_row(row: &rusqlite::Row) -> rusqlite::Result<ImportTemplate> {
+    let name: String = row.get(1)?;
+    let mapping: String = row.get(5)?;
+    // A mapping that no longer parses must not load as an empty one
+    let mapping = serde_json::from_str(&mapping).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(
+            5,
+            rusqlite::types::Type::Text,
+            format!("Import template \"{}\" has an unreadable column mapping: {}", name, e).into(),
+        )
+    })?;
+    Ok(ImportTemplate {
+        id: row.get(0)?,
+        name,
+        organizer: row.get(2)?,
+        sheet_name: row.get(3)?,
+        header_row: row.get(4)?,
+        mapping,
+        created_at: row.get(6)?,
+    })
+}
+
+// Templates of one organiser when given, otherwise all of them
+#[tauri::command]
+pub fn get_import_templates(db: State<Database>, organizer: Option<String>) -> Result<Vec<ImportTemplate>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM import_templates
+             WHERE ?1 IS NULL OR organizer = ?1 COLLATE NOCASE
+             ORDER BY organizer COLLATE NOCASE, name COLLATE NOCASE",
+            TEMPLATE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let templates = stmt
+        .query_map([organizer], template_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(templates)
+}
+
+#[tauri::command]
+pub fn save_import_template(db: State<Database>, request: SaveImportTemplateRequest) -> Result<ImportTemplate, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let mapping = serde_json::to_string(&request.mapping).map_err(|e| e.to_string())?;
+
+    let id = match request.id {
+        Some(id) => {
+            conn.execute(
+                "UPDATE import_templates SET name = ?1, organizer = ?2, sheet_name = ?3, header_row = ?4, mapping = ?5
+                 WHERE id = ?6",
+                rusqlite::params![request.name, request.organizer, request.sheet_name, request.header_row, mapping, id],
+            )
+            .map_err(|e| e.to_string())?;
+            id
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO import_templates (name, organizer, sheet_name, header_row, mapping)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![request.name, request.organizer, request.sheet_name, request.header_row, mapping],
+            )
+            .map_err(|e| e.to_string())?;
+            conn.last_insert_rowid()
+        }
+    };
+
+    conn.query_row(
+        &format!("SELECT {} FROM import_templates WHERE id = ?1", TEMPLATE_COLUMNS),
+        [id],
+        template_from_row,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_import_template(db: State<Database>, id: i64) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM import_templates WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ==================== PDF IMPORT COMMANDS ====================
+
+struct PdfProposal {
+    pages: usize,
+    lines_read: usize,
+    candidates: Vec<PdfCandidate>,
+    accepted_lines: Vec<usize>,
+}
+
+fn read_pdf_proposal(path: &str, options: &PdfImportOptions) -> Result<PdfProposal, String> {
+    let (pages, lines) = pdf_import::extract_lines(path)?;
+    let candidates = pdf_import::detect(&lines);
+
+    let min_confidence = options.min_confidence.unwrap_or(pdf_import::DEFAULT_MIN_CONFIDENCE);
+    let accepted_lines = candidates
+        .iter()
+        .filter(|candidate| match &options.lines {
+            Some(lines) => lines.contains(&candidate.line),
+            None => candidate.confidence >= min_confidence,
+        })
+        .map(|candidate| candidate.line)
+        .collect();
+
+    Ok(PdfProposal { pages, lines_read: lines.len(), candidates, accepted_lines })
+}
+
+impl PdfProposal {
+    fn accepted(&self) -> Vec<&PdfCandidate> {
+        self.candidates.iter().filter(|c| self.accepted_lines.contains(&c.line)).collect()
+    }
+}
+
+#[tauri::command]
+pub fn preview_pdf_import(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    options: PdfImportOptions,
+) -> Result<PdfImportPreview, String> {
+    let mut proposal = read_pdf_proposal(&path, &options)?;
+    let replace_existing = options.replace_existing.unwrap_or(false);
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    // Proposals point at the PC they would land in
+    let pcs = query_pcs(&conn, race_id)?;
+    for candidate in &mut proposal.candidates {
+        if let Some(pc) = pcs.iter().find(|pc| pc.pc_number == candidate.pc_number) {
+            candidate.reference.pc_id = pc.id;
+        }
+    }
+
+    let accepted = proposal.accepted();
+    let mut pc_numbers: Vec<i32> = Vec::new();
+    for candidate in &accepted {
+        if !pc_numbers.contains(&candidate.pc_number) {
+            pc_numbers.push(candidate.pc_number);
+        }
+    }
+
+    let mut diff = Vec::new();
+    for pc_number in pc_numbers {
+        let existing = match pcs.iter().find(|pc| pc.pc_number == pc_number) {
+            Some(pc) => query_references(&conn, pc.id)?,
+            None => Vec::new(),
+        };
+        let proposed: Vec<&PdfCandidate> = accepted.iter().copied().filter(|c| c.pc_number == pc_number).collect();
+        diff.extend(pdf_import::diff(pc_number, &existing, &proposed, replace_existing));
+    }
+
+    let report = import_roadbook(&mut conn, race_id, pdf_import::to_table(&accepted), HashMap::new(), replace_existing, true)?;
+
+    let mut candidates = proposal.candidates;
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then(a.line.cmp(&b.line)));
+
+    Ok(PdfImportPreview {
+        pages: proposal.pages,
+        lines_read: proposal.lines_read,
+        candidates,
+        accepted_lines: proposal.accepted_lines,
+        diff,
+        report,
+    })
+}
+
+#[tauri::command]
+pub fn import_pdf_references(
+    db: State<Database>,
+    race_id: i64,
+    path: String,
+    options: PdfImportOptions,
+) -> Result<RoadbookImportReport, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    ensure_race_unlocked(&conn, race_id)?;
+    let proposal = read_pdf_proposal(&path, &options)?;
+    if proposal.accepted_lines.is_empty() {
+        return Err("No references were accepted from the PDF".to_string().into());
+    }
+
+    backup::create(&conn, &db.backup_dir, backup::BEFORE_IMPORT)?;
+    let table = pdf_import::to_table(&proposal.accepted());
+    Ok(import_roadbook(&mut conn, race_id, table, HashMap::new(), options.replace_existing.unwrap_or(false), false)?)
+}
+
+// ==================== BUNDLE COMMANDS ====================
+
+// Per-race settings, keyed without the race prefix
+fn query_race_settings(conn: &Connection, race_id: i64) -> Result<HashMap<String, String>, String> {
+    let prefix = bundle::race_setting_prefix(race_id);
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM user_preferences WHERE substr(key, 1, length(?1)) = ?1")
+        .map_err(|e| e.to_string())?;
+    let settings = stmt
+        .query_map([&prefix], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?
+        .map(|row| row.map(|(key, value)| (key[prefix.len()..].to_string(), value)))
+        .collect::<Result<HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(settings)
+}
+
+fn build_race_bundle(conn: &Connection, race_id: i64) -> Result<RaceBundle, String> {
+    let race = query_race(conn, race_id)?;
+
+    let mut pcs = Vec::new();
+    for pc in query_pcs(conn, race_id)? {
+        let references = query_references(conn, pc.id)?;
+        let tulips = query_pc_tulips(conn, pc.id)?;
+        pcs.push(BundlePC { pc, references, tulips });
+    }
+
+    let settings = query_race_settings(conn, race_id)?;
+
+    let exported_at: String = conn
+        .query_row("SELECT datetime('now')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    Ok(RaceBundle {
+        format: bundle::BUNDLE_FORMAT.to_string(),
+        version: bundle::BUNDLE_VERSION,
+        exported_at,
+        race,
+        pcs,
+        settings,
+    })
+}
+
+// Inserts a PC of a bundle with new ids, returns how many references it had
+fn insert_bundle_pc(conn: &Connection, race_id: i64, bundle_pc: &BundlePC) -> Result<usize, String> {
+    let pc = &bundle_pc.pc;
+    conn.execute(
+        "INSERT INTO pcs (race_id, pc_number, name, pc_type, start_location, scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            race_id,
+            pc.pc_number,
+            pc.name,
+            pc.pc_type,
+            pc.start_location,
+            pc.scheduled_start_centiseconds,
+            pc.target_time_centiseconds,
+            pc.total_distance_meters,
+            pc.notes
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let pc_id = conn.last_insert_rowid();
+
+    let mut reference_ids = HashMap::new();
+    for (index, reference) in bundle_pc.references.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO reference_entries (pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            rusqlite::params![
+                pc_id,
+                reference.hours,
+                reference.minutes,
+                reference.seconds,
+                reference.centiseconds,
+                reference.event_type,
+                reference.speed,
+                reference.extra_value,
+                reference.is_control_zone,
+                index as i32,
+                reference.defined_by,
+                reference.distance_meters,
+                reference.note,
+                reference.landmark
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        reference_ids.insert(reference.id, conn.last_insert_rowid());
+    }
+
+    for tulip in &bundle_pc.tulips {
+        let reference_id = reference_ids
+            .get(&tulip.reference_id)
+            .ok_or_else(|| format!("PC {}: tulip for unknown reference {}", pc.pc_number, tulip.reference_id))?;
+        conn.execute(
+            "INSERT INTO reference_tulips (reference_id, mime_type, data, description) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![reference_id, tulip.mime_type, tulip.data, tulip.description],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    recompute_pc_route(conn, pc_id)?;
+    record_pc_revision(conn, pc_id, "import_race_bundle")?;
+    Ok(bundle_pc.references.len())
+}
+
+// How the PCs of a bundle compare with those of the race holding its
+// identity, using the roadbook checksum so ids and notes don't count
+fn compare_bundle_pcs(conn: &Connection, race_id: i64, bundle: &RaceBundle) -> Result<Vec<BundlePCConflict>, String> {
+    let local_pcs = query_pcs(conn, race_id)?;
+    let mut conflicts = Vec::new();
+
+    for bundle_pc in &bundle.pcs {
+        let number = bundle_pc.pc.pc_number;
+        let bundle_sum = checksum::pc_checksum(&bundle_pc.pc, &bundle_pc.references);
+        let (status, local_references) = match local_pcs.iter().find(|pc| pc.pc_number == number) {
+            Some(pc) => {
+                let references = query_references(conn, pc.id)?;
+                let same = checksum::pc_checksum(pc, &references).hash == bundle_sum.hash;
+                (if same { "same" } else { "changed" }, references.len())
+            }
+            None => ("new", 0),
+        };
+        conflicts.push(BundlePCConflict {
+            pc_number: number,
+            status: status.to_string(),
+            local_references,
+            bundle_references: bundle_pc.references.len(),
+        });
+    }
+    for pc in local_pcs.iter().filter(|pc| !bundle.pcs.iter().any(|b| b.pc.pc_number == pc.pc_number)) {
+        conflicts.push(BundlePCConflict {
+            pc_number: pc.pc_number,
+            status: "local".to_string(),
+            local_references: query_references(conn, pc.id)?.len(),
+            bundle_references: 0,
+        });
+    }
+
+    conflicts.sort_by_key(|conflict| conflict.pc_number);
+    Ok(conflicts)
+}
+
+// Writes a bundle into the database. Without a mode, a race with the same uid
+// is reported as a conflict, PC by PC, and nothing is written. "replace"
+// overwrites that race, "merge" adds the bundle's PCs to it (replacing those
+// with the same number) and "copy" imports the bundle as a new race. Replaced
+// PCs go to the trash, with their revisions.
+fn apply_race_bundle(
+    conn: &mut Connection,
+    backup_dir: &Path,
+    bundle: &RaceBundle,
+    mode: Option<&str>,
+) -> Result<RaceBundleImportReport, CommandError> {
+    if let Some(mode) = mode.filter(|mode| !matches!(*mode, "replace" | "merge" | "copy")) {
+        return Err(format!("Unknown import mode: {}", mode).into());
+    }
+
+    let source = &bundle.race;
+    let existing: Option<i64> = conn
+        .query_row("SELECT id FROM races WHERE uid = ?1 AND deleted_at IS NULL", [&source.uid], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    // A locked race is refused before anything is written, backup included
+    if let (Some(id), Some("replace" | "merge")) = (existing, mode) {
+        ensure_race_unlocked(conn, id)?;
+    }
+    // A conflict report writes nothing
+    if existing.is_none() || mode.is_some() {
+        backup::create(conn, backup_dir, backup::BEFORE_IMPORT)?;
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let mut report = RaceBundleImportReport {
+        applied: false,
+        race: None,
+        conflict: None,
+        pc_conflicts: Vec::new(),
+        pcs_created: Vec::new(),
+        pcs_replaced: Vec::new(),
+        references_created: 0,
+        settings_imported: 0,
+    };
+
+    let mut before = EditState::default();
+    let race_id = match (existing, mode) {
+        (Some(id), None) => {
+            report.conflict = Some(query_race(&tx, id)?);
+            report.pc_conflicts = compare_bundle_pcs(&tx, id, bundle)?;
+            return Ok(report);
+        }
+        (Some(id), Some("replace" | "merge")) => {
+            before = capture_race_edit(&tx, id)?;
+            if mode == Some("replace") {
+                tx.execute(
+                    "UPDATE races
+                     SET name = ?1, event_date = ?2, event_end_date = ?3, location = ?4, organizer = ?5,
+                         category = ?6, car_number = ?7, driver_name = ?8, navigator_name = ?9, notes = ?10
+                     WHERE id = ?11",
+                    rusqlite::params![
+                        source.name,
+                        source.event_date,
+                        source.event_end_date,
+                        source.location,
+                        source.organizer,
+                        source.category,
+                        source.car_number,
+                        source.driver_name,
+                        source.navigator_name,
+                        source.notes,
+                        id
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                tx.execute(
+                    "DELETE FROM user_preferences WHERE substr(key, 1, length(?1)) = ?1",
+                    [bundle::race_setting_prefix(id)],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            id
+        }
+        _ => {
+            // A copy next to the original, or next to a race in the trash with
+            // the same identity, gets a fresh one from the trigger
+            let taken: bool = tx
+                .query_row("SELECT EXISTS(SELECT 1 FROM races WHERE uid = ?1)", [&source.uid], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            let uid = if taken { None } else { Some(&source.uid) };
+            tx.execute(
+                "INSERT INTO races (uid, name, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                rusqlite::params![
+                    uid,
+                    source.name,
+                    source.event_date,
+                    source.event_end_date,
+                    source.location,
+                    source.organizer,
+                    source.category,
+                    source.car_number,
+                    source.driver_name,
+                    source.navigator_name,
+                    source.notes
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            let id = tx.last_insert_rowid();
+            before.created_race(id, true);
+            id
+        }
+    };
+
+    let existing_pcs = query_pcs(&tx, race_id)?;
+    if mode == Some("replace") {
+        tx.execute(
+            "UPDATE pcs SET deleted_at = datetime('now') WHERE race_id = ?1 AND deleted_at IS NULL",
+            [race_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    for bundle_pc in &bundle.pcs {
+        let number = bundle_pc.pc.pc_number;
+        match existing_pcs.iter().find(|pc| pc.pc_number == number) {
+            Some(pc) => {
+                if mode == Some("merge") {
+                    tx.execute("UPDATE pcs SET deleted_at = datetime('now') WHERE id = ?1", [pc.id])
+                        .map_err(|e| e.to_string())?;
+                }
+                report.pcs_replaced.push(number);
+            }
+            None => report.pcs_created.push(number),
+        }
+        report.references_created += insert_bundle_pc(&tx, race_id, bundle_pc)?;
+    }
+
+    let prefix = bundle::race_setting_prefix(race_id);
+    for (key, value) in &bundle.settings {
+        tx.execute(
+            "INSERT OR REPLACE INTO user_preferences (key, value) VALUES (?1, ?2)",
+            rusqlite::params![format!("{}{}", prefix, key), value],
+        )
+        .map_err(|e| e.to_string())?;
+        report.settings_imported += 1;
+    }
+
+    record_race_revision(&tx, race_id, "import_race_bundle")?;
+    record_edit(&tx, "import_race_bundle", before)?;
+    report.race = Some(query_race(&tx, race_id)?);
+    tx.commit().map_err(|e| e.to_string())?;
+    report.applied = true;
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn export_race_bundle(db: State<Database>, race_id: i64, path: String) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let bundle = build_race_bundle(&conn, race_id)?;
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn import_race_bundle(db: State<Database>, path: String, mode: Option<String>) -> Result<RaceBundleImportReport, CommandError> {
+    let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let bundle = bundle::parse(&json)?;
+
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    apply_race_bundle(&mut conn, &db.backup_dir, &bundle, mode.as_deref())
+}
+
+// ==================== QR TRANSFER COMMANDS ====================
+
+#[tauri::command]
+pub fn export_race_qr(db: State<Database>, race_id: i64, chunk_size: Option<usize>) -> Result<Vec<QrChunk>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let bundle = build_race_bundle(&conn, race_id)?;
+    let json = serde_json::to_string(&bundle).map_err(|e| e.to_string())?;
+    qr_transfer::encode(&json, chunk_size)
+}
+
+// Payloads scanned so far, in any order and with repeats
+#[tauri::command]
+pub fn read_race_qr(payloads: Vec<String>) -> Result<QrTransferStatus, String> {
+    let (mut status, json) = qr_transfer::decode(&payloads)?;
+    if let Some(json) = json {
+        status.race_name = Some(bundle::parse(&json)?.race.name);
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn import_race_qr(db: State<Database>, payloads: Vec<String>, mode: Option<String>) -> Result<RaceBundleImportReport, CommandError> {
+    let (status, json) = qr_transfer::decode(&payloads)?;
+    let Some(json) = json else {
+        let missing: Vec<String> = status.missing.iter().map(|index| index.to_string()).collect();
+        return Err(format!("Codes {} of {} are missing", missing.join(", "), status.total).into());
+    };
+    let bundle = bundle::parse(&json)?;
+
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    apply_race_bundle(&mut conn, &db.backup_dir, &bundle, mode.as_deref())
+}
+
+// ==================== BACKUP COMMANDS ====================
+
+#[tauri::command]
+pub fn create_backup(db: State<Database>) -> Result<BackupInfo, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    backup::create(&conn, &db.backup_dir, backup::MANUAL)
+}
+
+// Newest first
+#[tauri::command]
+pub fn get_backups(db: State<Database>) -> Result<Vec<BackupInfo>, String> {
+    backup::list(&db.backup_dir)
+}
+
+#[tauri::command]
+pub fn get_backup_races(db: State<Database>, file_name: String) -> Result<Vec<BackupRace>, String> {
+    let conn = backup::open(&db.backup_dir, &file_name)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT r.id, r.uid, r.name, r.event_date,
+                    (SELECT COUNT(*) FROM pcs WHERE race_id = r.id AND deleted_at IS NULL),
+                    (SELECT COUNT(*) FROM reference_entries e JOIN pcs p ON p.id = e.pc_id
+                     WHERE p.race_id = r.id AND p.deleted_at IS NULL),
+                    r.locked_at, r.deleted_at
+             FROM races r
+             ORDER BY r.created_at DESC, r.id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let races = stmt
+        .query_map([], |row| {
+            Ok(BackupRace {
+                id: row.get(0)?,
+                uid: row.get(1)?,
+                name: row.get(2)?,
+                event_date: row.get(3)?,
+                pc_count: row.get(4)?,
+                reference_count: row.get(5)?,
+                locked_at: row.get(6)?,
+                deleted_at: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(races)
+}
+
+// Puts the whole database back as it was in the backup. The current one is
+// backed up first, so the restore itself can be rolled back. Refused while
+// any race is locked. The restored data gets the same fix-ups as at startup.
+#[tauri::command]
+pub fn restore_backup(db: State<Database>, file_name: String) -> Result<(), CommandError> {
+    {
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        ensure_no_race_locked(&conn)?;
+        backup::create(&conn, &db.backup_dir, backup::BEFORE_RESTORE)?;
+        backup::restore(&mut conn, &db.backup_dir, &file_name)?;
+    }
+    // The backup may predate the startup fix-ups, run them again on it
+    recompute_stale_routes(&db)?;
+    record_initial_revisions(&db)?;
+    purge_expired_trash(&db)?;
+    Ok(())
+}
+
+// Brings one race of a backup into the live database, as a bundle import
+// with the same modes. The race keeps its identity, so without a mode the
+// live copy of it is reported as a conflict.
+#[tauri::command]
+pub fn extract_backup_race(
+    db: State<Database>,
+    file_name: String,
+    race_id: i64,
+    mode: Option<String>,
+) -> Result<RaceBundleImportReport, CommandError> {
+    let bundle = build_race_bundle(&backup::open(&db.backup_dir, &file_name)?, race_id)?;
+
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    apply_race_bundle(&mut conn, &db.backup_dir, &bundle, mode.as_deref())
+}
+
+// ==================== INTEGRITY COMMANDS ====================
+
+// Rows of `PRAGMA integrity_check` other than the single "ok"
+fn check_file_integrity(conn: &Connection) -> Result<Vec<IntegrityIssue>, String> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check").map_err(|e| e.to_string())?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .map(|message| IntegrityIssue {
+            kind: "integrity".to_string(),
+            message,
+            race_id: None,
+            pc_id: None,
+            rows: 0,
+            repairable: false,
+        })
+        .collect())
+}
+
+// Rows pointing at a race, PC or reference that is gone, left by builds that
+// did not enforce foreign keys. Grouped by table, with their rowids.
+fn query_orphans(conn: &Connection) -> Result<Vec<(String, String, Vec<i64>)>, String> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check").map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut orphans: Vec<(String, String, Vec<i64>)> = Vec::new();
+    for (table, rowid, parent) in rows {
+        let Some(rowid) = rowid else { continue };
+        match orphans.iter_mut().find(|(t, p, _)| *t == table && *p == parent) {
+            Some((_, _, rowids)) => rowids.push(rowid),
+            None => orphans.push((table, parent, vec![rowid])),
+        }
+    }
+    Ok(orphans)
+}
+
+// A PC whose references don't hold the positions 0..n, each once
+struct BrokenOrder {
+    pc_id: i64,
+    race_id: i64,
+    pc_number: i32,
+    race_name: String,
+    duplicated: bool,
+}
+
+fn query_broken_orders(conn: &Connection) -> Result<Vec<BrokenOrder>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT p.id, p.race_id, p.pc_number, r.name, COUNT(*) != COUNT(DISTINCT e.order_index)
+             FROM reference_entries e
+             JOIN pcs p ON p.id = e.pc_id
+             JOIN races r ON r.id = p.race_id
+             GROUP BY e.pc_id
+             HAVING COUNT(*) != COUNT(DISTINCT e.order_index)
+                 OR MIN(e.order_index) != 0
+                 OR MAX(e.order_index) != COUNT(*) - 1
+             ORDER BY r.id, p.pc_number",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let pcs = stmt
+        .query_map([], |row| {
+            Ok(BrokenOrder {
+                pc_id: row.get(0)?,
+                race_id: row.get(1)?,
+                pc_number: row.get(2)?,
+                race_name: row.get(3)?,
+                duplicated: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(pcs)
+}
+
+fn check_integrity(conn: &Connection) -> Result<Vec<IntegrityIssue>, String> {
+    let mut issues = check_file_integrity(conn)?;
+
+    for (table, parent, rowids) in query_orphans(conn)? {
+        let kind = if table == "reference_entries" { "orphan_reference" } else { "foreign_key" };
+        issues.push(IntegrityIssue {
+            kind: kind.to_string(),
+            message: format!("{} rows of {} point at missing {}", rowids.len(), table, parent),
+            race_id: None,
+            pc_id: None,
+            rows: rowids.len(),
+            repairable: true,
+        });
+    }
+
+    for pc in query_broken_orders(conn)? {
+        let (kind, problem) = if pc.duplicated {
+            ("duplicate_order", "references share an order position")
+        } else {
+            ("order_gap", "the order of the references has gaps")
+        };
+        issues.push(IntegrityIssue {
+            kind: kind.to_string(),
+            message: format!("{}, PC {}: {}", pc.race_name, pc.pc_number, problem),
+            race_id: Some(pc.race_id),
+            pc_id: Some(pc.pc_id),
+            rows: query_reference_ids(conn, pc.pc_id)?.len(),
+            repairable: true,
+        });
+    }
+
+    Ok(issues)
+}
+
+// Runs SQLite's own checks and looks for rows the app would trip over
+#[tauri::command]
+pub fn check_database(db: State<Database>) -> Result<IntegrityReport, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let issues = check_integrity(&conn)?;
+
+    Ok(IntegrityReport {
+        ok: issues.is_empty(),
+        issues,
+        repaired: Vec::new(),
+        backup: None,
+    })
+}
+
+// Deletes orphan rows and renumbers broken reference orders, keeping the
+// order the references had. A damaged file is left alone: that takes a
+// backup restore.
+#[tauri::command]
+pub fn repair_database(db: State<Database>) -> Result<IntegrityReport, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    if !check_file_integrity(&conn)?.is_empty() {
+        return Err("The database file is damaged and cannot be repaired, restore a backup".to_string());
+    }
+
+    let found = check_integrity(&conn)?;
+    if found.is_empty() {
+        return Ok(IntegrityReport { ok: true, issues: Vec::new(), repaired: Vec::new(), backup: None });
+    }
+    let backup = backup::create(&conn, &db.backup_dir, backup::BEFORE_REPAIR)?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    // Deleting an orphan PC takes its references with it through the
+    // cascade, so this checks again until nothing points at a missing row
+    loop {
+        let orphans = query_orphans(&tx)?;
+        if orphans.is_empty() {
+            break;
+        }
+        for (table, _, rowids) in orphans {
+            for rowid in rowids {
+                tx.execute(&format!("DELETE FROM \"{}\" WHERE rowid = ?1", table), [rowid])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    for pc in query_broken_orders(&tx)? {
+        let ids = query_reference_ids(&tx, pc.pc_id)?;
+        write_reference_order(&tx, &ids)?;
+        record_pc_revision(&tx, pc.pc_id, "repair_database")?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let issues = check_integrity(&conn)?;
+    Ok(IntegrityReport {
+        ok: issues.is_empty(),
+        repaired: found.into_iter().filter(|issue| !issues.iter().any(|left| left.message == issue.message)).collect(),
+        issues,
+        backup: Some(backup),
+    })
+}
+
+// ==================== REVISION COMMANDS ====================
+
+const REVISION_COLUMNS: &str = "id, race_id, pc_id, action, created_at";
+
+fn revision_from_row(row: &rusqlite::Row) -> rusqlite::Result<Revision> {
+    Ok(Revision {
+        id: row.get(0)?,
+        race_id: row.get(1)?,
+        pc_id: row.get(2)?,
+        action: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn query_revision(conn: &Connection, id: i64) -> Result<(Revision, String), String> {
+    conn.query_row(
+        &format!("SELECT {}, snapshot FROM revisions WHERE id = ?1", REVISION_COLUMNS),
+        [id],
+        |row| Ok((revision_from_row(row)?, row.get(5)?)),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Stores a snapshot unless it is the same as the latest one of that race or PC
+fn insert_revision(conn: &Connection, race_id: i64, pc_id: Option<i64>, action: &str, snapshot: String) -> Result<(), String> {
+    let latest: Option<String> = conn
+        .query_row(
+            "SELECT snapshot FROM revisions WHERE race_id = ?1 AND pc_id IS ?2 ORDER BY id DESC LIMIT 1",
+            rusqlite::params![race_id, pc_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if latest.as_ref() == Some(&snapshot) {
+        return Ok(());
+    }
+
+    conn.execute(
+        "INSERT INTO revisions (race_id, pc_id, action, snapshot) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![race_id, pc_id, action, snapshot],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn record_race_revision(conn: &Connection, race_id: i64, action: &str) -> Result<(), String> {
+    let race = query_race(conn, race_id)?;
+    let snapshot = serde_json::to_string(&race).map_err(|e| e.to_string())?;
+    insert_revision(conn, race_id, None, action, snapshot)
+}
+
+fn pc_snapshot(conn: &Connection, pc_id: i64) -> Result<PCSnapshot, String> {
+    Ok(PCSnapshot {
+        pc: query_pc(conn, pc_id)?,
+        references: query_references(conn, pc_id)?,
+        tulips: Some(query_pc_tulips(conn, pc_id)?),
+    })
+}
+
+fn record_pc_revision(conn: &Connection, pc_id: i64, action: &str) -> Result<(), String> {
+    let snapshot = pc_snapshot(conn, pc_id)?;
+    let json = serde_json::to_string(&snapshot).map_err(|e| e.to_string())?;
+    insert_revision(conn, snapshot.pc.race_id, Some(pc_id), action, json)
+}
+
+// Gives races and PCs created before revisions existed a starting point
+pub fn record_initial_revisions(db: &Database) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let ids = |sql: &str| -> Result<Vec<i64>, String> {
+        let mut stmt = tx.prepare(sql).map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(ids)
+    };
+    let race_ids = ids("SELECT id FROM races WHERE id NOT IN (SELECT race_id FROM revisions WHERE pc_id IS NULL)")?;
+    let pc_ids = ids("SELECT id FROM pcs WHERE id NOT IN (SELECT pc_id FROM revisions WHERE pc_id IS NOT NULL)")?;
+
+    for race_id in race_ids {
+        record_race_revision(&tx, race_id, "initial")?;
+    }
+    for pc_id in pc_ids {
+        record_pc_revision(&tx, pc_id, "initial")?;
+    }
+
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// Newest first. With a PC, only its revisions; otherwise the race's and all its PCs'
+#[tauri::command]
+pub fn get_revisions(db: State<Database>, race_id: i64, pc_id: Option<i64>) -> Result<Vec<Revision>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM revisions WHERE race_id = ?1 AND (?2 IS NULL OR pc_id = ?2) ORDER BY id DESC",
+            REVISION_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let revisions = stmt
+        .query_map(rusqlite::params![race_id, pc_id], revision_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(revisions)
+}
+
+#[tauri::command]
+pub fn get_pc_revision(db: State<Database>, id: i64) -> Result<PCSnapshot, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let (revision, snapshot) = query_revision(&conn, id)?;
+    if revision.pc_id.is_none() {
+        return Err(format!("Revision {} is not a PC revision", id));
+    }
+    serde_json::from_str(&snapshot).map_err(|e| e.to_string())
+}
+
+// Changes from one revision of a PC to a later one, or to the PC as it is now
+#[tauri::command]
+pub fn diff_pc_revisions(db: State<Database>, from_id: i64, to_id: Option<i64>) -> Result<PCRevisionDiff, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+
+    let (from, snapshot) = query_revision(&conn, from_id)?;
+    let pc_id = from.pc_id.ok_or_else(|| format!("Revision {} is not a PC revision", from_id))?;
+    let before: PCSnapshot = serde_json::from_str(&snapshot).map_err(|e| e.to_string())?;
+
+    let (to, after) = match to_id {
+        Some(to_id) => {
+            let (to, snapshot) = query_revision(&conn, to_id)?;
+            if to.pc_id != Some(pc_id) {
+                return Err("Both revisions must belong to the same PC".to_string());
+            }
+            (Some(to), serde_json::from_str(&snapshot).map_err(|e| e.to_string())?)
+        }
+        None => (None, pc_snapshot(&conn, pc_id)?),
+    };
+
+    Ok(PCRevisionDiff {
+        pc_fields: revisions::field_changes(&before.pc, &after.pc),
+        references: revisions::reference_changes(&before, &after),
+        from,
+        to,
+    })
+}
+
+// Puts a PC's references back as they were, keeping their ids where possible.
+// Tulips are written back onto whichever reference each one was restored as.
+fn restore_pc_snapshot(conn: &Connection, snapshot: &PCSnapshot) -> Result<(), String> {
+    let pc = &snapshot.pc;
+    // The number is left alone, the race may have been renumbered since
+    conn.execute(
+        "UPDATE pcs
+         SET name = ?1, pc_type = ?2, start_location = ?3, scheduled_start_centiseconds = ?4,
+             target_time_centiseconds = ?5, total_distance_meters = ?6, notes = ?7
+         WHERE id = ?8",
+        rusqlite::params![
+            pc.name,
+            pc.pc_type,
+            pc.start_location,
+            pc.scheduled_start_centiseconds,
+            pc.target_time_centiseconds,
+            pc.total_distance_meters,
+            pc.notes,
+            pc.id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    for id in query_reference_ids(conn, pc.id)? {
+        if !snapshot.references.iter().any(|r| r.id == id) {
+            conn.execute("DELETE FROM reference_entries WHERE id = ?1", [id])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut restored: Vec<(i64, i64)> = Vec::new();
+    for reference in &snapshot.references {
+        let written = conn
+            .execute(
+                "INSERT INTO reference_entries (id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+                 ON CONFLICT(id) DO UPDATE SET
+                     hours = excluded.hours, minutes = excluded.minutes, seconds = excluded.seconds,
+                     centiseconds = excluded.centiseconds, event_type = excluded.event_type, speed = excluded.speed,
+                     extra_value = excluded.extra_value, is_control_zone = excluded.is_control_zone,
+                     order_index = excluded.order_index, defined_by = excluded.defined_by,
+                     distance_meters = excluded.distance_meters, note = excluded.note, landmark = excluded.landmark
+                 WHERE pc_id = excluded.pc_id",
+                rusqlite::params![
+                    reference.id,
+                    pc.id,
+                    reference.hours,
+                    reference.minutes,
+                    reference.seconds,
+                    reference.centiseconds,
+                    reference.event_type,
+                    reference.speed,
+                    reference.extra_value,
+                    reference.is_control_zone,
+                    reference.order_index,
+                    reference.defined_by,
+                    reference.distance_meters,
+                    reference.note,
+                    reference.landmark
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+
+        // The id now belongs to a reference of another PC, restored under a new one
+        if written == 0 {
+            conn.execute(
+                "INSERT INTO reference_entries (pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                rusqlite::params![
+                    pc.id,
+                    reference.hours,
+                    reference.minutes,
+                    reference.seconds,
+                    reference.centiseconds,
+                    reference.event_type,
+                    reference.speed,
+                    reference.extra_value,
+                    reference.is_control_zone,
+                    reference.order_index,
+                    reference.defined_by,
+                    reference.distance_meters,
+                    reference.note,
+                    reference.landmark
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            restored.push((reference.id, conn.last_insert_rowid()));
+        } else {
+            restored.push((reference.id, reference.id));
+        }
+    }
+
+    if let Some(tulips) = &snapshot.tulips {
+        for (snapshot_id, id) in restored {
+            let current = query_reference_tulip(conn, id)?;
+            match tulips.iter().find(|tulip| tulip.reference_id == snapshot_id) {
+                Some(tulip) => {
+                    let unchanged = current.is_some_and(|current| {
+                        (&current.mime_type, &current.data, &current.description) == (&tulip.mime_type, &tulip.data, &tulip.description)
+                    });
+                    if !unchanged {
+                        conn.execute(
+                            "INSERT INTO reference_tulips (reference_id, mime_type, data, description, created_at)
+                             VALUES (?1, ?2, ?3, ?4, ?5)
+                             ON CONFLICT(reference_id) DO UPDATE
+                             SET mime_type = excluded.mime_type, data = excluded.data, description = excluded.description,
+                                 created_at = excluded.created_at",
+                            rusqlite::params![id, tulip.mime_type, tulip.data, tulip.description, tulip.created_at],
+                        )
+                        .map_err(|e| e.to_string())?;
+                    }
+                }
+                None if current.is_some() => {
+                    conn.execute("DELETE FROM reference_tulips WHERE reference_id = ?1", [id])
+                        .map_err(|e| e.to_string())?;
+                }
+                None => {}
+            }
+        }
+    }
+
+    recompute_pc_route(conn, pc.id)
+}
+
+// Brings a race or a PC back to an older revision. The restore is recorded as
+// a new revision, so it can be undone by restoring the one before it.
+#[tauri::command]
+pub fn restore_revision(db: State<Database>, id: i64) -> Result<Revision, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let (revision, snapshot) = query_revision(&tx, id)?;
+    ensure_race_unlocked(&tx, revision.race_id)?;
+    let before = match revision.pc_id {
+        Some(pc_id) => capture_edit(&tx, &[], &[pc_id], true)?,
+        None => capture_edit(&tx, &[revision.race_id], &[], false)?,
+    };
+    match revision.pc_id {
+        Some(pc_id) => {
+            let snapshot: PCSnapshot = serde_json::from_str(&snapshot).map_err(|e| e.to_string())?;
+            restore_pc_snapshot(&tx, &snapshot)?;
+            record_pc_revision(&tx, pc_id, "restore_revision")?;
+        }
+        None => {
+            let race: Race = serde_json::from_str(&snapshot).map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE races
+                 SET name = ?1, event_date = ?2, event_end_date = ?3, location = ?4, organizer = ?5,
+                     category = ?6, car_number = ?7, driver_name = ?8, navigator_name = ?9, notes = ?10
+                 WHERE id = ?11",
+                rusqlite::params![
+                    race.name,
+                    race.event_date,
+                    race.event_end_date,
+                    race.location,
+                    race.organizer,
+                    race.category,
+                    race.car_number,
+                    race.driver_name,
+                    race.navigator_name,
+                    race.notes,
+                    revision.race_id
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            record_race_revision(&tx, revision.race_id, "restore_revision")?;
+        }
+    }
+    record_edit(&tx, "restore_revision", before)?;
+
+    let latest: Revision = tx
+        .query_row(
+            &format!(
+                "SELECT {} FROM revisions WHERE race_id = ?1 AND pc_id IS ?2 ORDER BY id DESC LIMIT 1",
+                REVISION_COLUMNS
+            ),
+            rusqlite::params![revision.race_id, revision.pc_id],
+            revision_from_row,
+        )
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(latest)
+}
+
+// ==================== UNDO COMMANDS ====================
+
+// Edits kept for undo, older ones are dropped
+const EDIT_HISTORY_LIMIT: i64 = 100;
+
+const EDIT_HISTORY_COLUMNS: &str = "id, action, race_id, undone, created_at";
+
+fn edit_history_from_row(row: &rusqlite::Row) -> rusqlite::Result<EditHistoryEntry> {
+    Ok(EditHistoryEntry {
+        id: row.get(0)?,
+        action: row.get(1)?,
+        race_id: row.get(2)?,
+        undone: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn capture_race(conn: &Connection, id: i64, whole: bool) -> Result<RaceEditState, String> {
+    let race = conn
+        .query_row(&format!("SELECT {} FROM races WHERE id = ?1", RACE_COLUMNS), [id], race_from_row)
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let settings = match race {
+        Some(_) if whole => Some(query_race_settings(conn, id)?),
+        _ => None,
+    };
+    Ok(RaceEditState { id, race, whole, settings })
+}
+
+// With `contents`, the references and tulips of the PC come along with its row
+fn capture_pc(conn: &Connection, id: i64, contents: bool) -> Result<PCEditState, String> {
+    let pc = conn
+        .query_row(&format!("SELECT {} FROM pcs WHERE id = ?1", PC_COLUMNS), [id], pc_from_row)
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let (references, tulips) = match pc {
+        Some(_) if contents => (Some(query_references(conn, id)?), query_pc_tulips(conn, id)?),
+        _ => (contents.then(Vec::new), Vec::new()),
+    };
+    Ok(PCEditState { id, pc, references, tulips })
+}
+
+// Race rows and PCs an edit is about to change
+fn capture_edit(conn: &Connection, race_ids: &[i64], pc_ids: &[i64], contents: bool) -> Result<EditState, String> {
+    Ok(EditState {
+        races: race_ids.iter().map(|&id| capture_race(conn, id, false)).collect::<Result<_, _>>()?,
+        pcs: pc_ids.iter().map(|&id| capture_pc(conn, id, contents)).collect::<Result<_, _>>()?,
+    })
+}
+
+// A race with all its PCs, references and tulips
+fn capture_race_edit(conn: &Connection, race_id: i64) -> Result<EditState, String> {
+    let mut state = EditState { races: vec![capture_race(conn, race_id, true)?], pcs: Vec::new() };
+    for pc in query_pcs(conn, race_id)? {
+        state.pcs.push(capture_pc(conn, pc.id, true)?);
+    }
+    Ok(state)
+}
+
+// Stores an edit for undo, once it is done: `before` is what was captured
+// before it, the same rows are captured again as they are now
+fn record_edit(conn: &Connection, action: &str, mut before: EditState) -> Result<(), String> {
+    // PCs added to a whole race by the edit did not exist before it
+    let whole_races: Vec<i64> = before.races.iter().filter(|race| race.whole).map(|race| race.id).collect();
+    for race_id in whole_races {
+        for pc in query_pcs(conn, race_id)? {
+            if !before.pcs.iter().any(|state| state.id == pc.id) {
+                before.created_pc(pc.id);
+            }
+        }
+    }
+
+    let mut after = EditState::default();
+    for race in &before.races {
+        after.races.push(capture_race(conn, race.id, race.whole)?);
+    }
+    for pc in &before.pcs {
+        after.pcs.push(capture_pc(conn, pc.id, pc.references.is_some())?);
+    }
+
+    // Tulips the edit did not change are left out of both sides
+    for (old, new) in before.pcs.iter_mut().zip(after.pcs.iter_mut()) {
+        let unchanged: Vec<i64> = old.tulips.iter().filter(|tulip| new.tulips.contains(tulip)).map(|tulip| tulip.id).collect();
+        old.tulips.retain(|tulip| !unchanged.contains(&tulip.id));
+        new.tulips.retain(|tulip| !unchanged.contains(&tulip.id));
+    }
+
+    let before_json = serde_json::to_value(&before).map_err(|e| e.to_string())?;
+    let after_json = serde_json::to_value(&after).map_err(|e| e.to_string())?;
+    if before_json == after_json {
+        return Ok(());
+    }
+
+    let race_id = after
+        .races
+        .first()
+        .map(|race| race.id)
+        .or_else(|| after.pcs.iter().chain(&before.pcs).find_map(|state| state.pc.as_ref().map(|pc| pc.race_id)));
+
+    // A new edit drops whatever was undone before it
+    conn.execute("DELETE FROM edit_history WHERE undone = 1", [])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO edit_history (action, race_id, before, after) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![action, race_id, before_json.to_string(), after_json.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM edit_history WHERE id <= (SELECT MAX(id) FROM edit_history) - ?1",
+        [EDIT_HISTORY_LIMIT],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Bulletins are undone with their own revert, so edits made before one is
+// applied or reverted can no longer be undone over it
+fn clear_edit_history(conn: &Connection, race_id: i64) -> Result<(), String> {
+    conn.execute("DELETE FROM edit_history WHERE race_id = ?1", [race_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Brings the rows of an edit from one side (`from`) back to the other (`to`),
+// with their original ids, numbers and order indexes
+fn restore_edit_state(conn: &Connection, from: &EditState, to: &EditState, action: &str) -> Result<(), CommandError> {
+    // Locked races are left alone, on whichever side of the edit they are
+    let mut race_ids: Vec<i64> = from.races.iter().chain(&to.races).map(|race| race.id).collect();
+    race_ids.extend(from.pcs.iter().chain(&to.pcs).filter_map(|state| state.pc.as_ref()).map(|pc| pc.race_id));
+    race_ids.sort_unstable();
+    race_ids.dedup();
+    for race_id in race_ids {
+        let exists = conn
+            .query_row("SELECT 1 FROM races WHERE id = ?1", [race_id], |_| Ok(()))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .is_some();
+        if exists {
+            ensure_race_unlocked(conn, race_id)?;
+        }
+    }
+
+    // The lock state is not part of an edit and is never written back
+    for state in &to.races {
+        let Some(race) = &state.race else { continue };
+        let existed = conn
+            .query_row("SELECT 1 FROM races WHERE id = ?1", [race.id], |_| Ok(()))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .is_some();
+        conn.execute(
+            "INSERT INTO races (id, uid, name, event_date, event_end_date, location, organizer, category, car_number, driver_name, navigator_name, notes, deleted_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             ON CONFLICT(id) DO UPDATE SET
+                 name = excluded.name, event_date = excluded.event_date, event_end_date = excluded.event_end_date,
+                 location = excluded.location, organizer = excluded.organizer, category = excluded.category,
+                 car_number = excluded.car_number, driver_name = excluded.driver_name,
+                 navigator_name = excluded.navigator_name, notes = excluded.notes, deleted_at = excluded.deleted_at",
+            rusqlite::params![
+                race.id,
+                race.uid,
+                race.name,
+                race.event_date,
+                race.event_end_date,
+                race.location,
+                race.organizer,
+                race.category,
+                race.car_number,
+                race.driver_name,
+                race.navigator_name,
+                race.notes,
+                race.deleted_at,
+                race.created_at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+
+        if let (false, Some(settings)) = (existed, &state.settings) {
+            let prefix = bundle::race_setting_prefix(race.id);
+            for (key, value) in settings {
+                conn.execute(
+                    "INSERT OR REPLACE INTO user_preferences (key, value) VALUES (?1, ?2)",
+                    [format!("{}{}", prefix, key), value.clone()],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    for state in to.pcs.iter().filter(|state| state.pc.is_none()) {
+        // Foreign key cascade will delete associated references
+        conn.execute("DELETE FROM pcs WHERE id = ?1", [state.id])
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Numbers go through negative values so UNIQUE(race_id, pc_number) holds at every step
+    let pcs: Vec<&PC> = to.pcs.iter().filter_map(|state| state.pc.as_ref()).collect();
+    for pc in &pcs {
+        conn.execute(
+            "INSERT INTO pcs (id, race_id, pc_number, name, pc_type, start_location, scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes, deleted_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT(id) DO UPDATE SET
+                 race_id = excluded.race_id, pc_number = excluded.pc_number, name = excluded.name,
+                 pc_type = excluded.pc_type, start_location = excluded.start_location,
+                 scheduled_start_centiseconds = excluded.scheduled_start_centiseconds,
+                 target_time_centiseconds = excluded.target_time_centiseconds,
+                 total_distance_meters = excluded.total_distance_meters, notes = excluded.notes,
+                 deleted_at = excluded.deleted_at",
+            rusqlite::params![
+                pc.id,
+                pc.race_id,
+                -pc.pc_number,
+                pc.name,
+                pc.pc_type,
+                pc.start_location,
+                pc.scheduled_start_centiseconds,
+                pc.target_time_centiseconds,
+                pc.total_distance_meters,
+                pc.notes,
+                pc.deleted_at,
+                pc.created_at
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for pc in &pcs {
+        conn.execute("UPDATE pcs SET pc_number = ?1 WHERE id = ?2", rusqlite::params![pc.pc_number, pc.id])
+            .map_err(|e| e.to_string())?;
+    }
+
+    for state in &to.pcs {
+        let (Some(pc), Some(references)) = (&state.pc, &state.references) else { continue };
+        for id in query_reference_ids(conn, pc.id)? {
+            if !references.iter().any(|reference| reference.id == id) {
+                conn.execute("DELETE FROM reference_entries WHERE id = ?1", [id])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        for reference in references {
+            conn.execute(
+                "INSERT INTO reference_entries (id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, defined_by, distance_meters, note, landmark, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+                 ON CONFLICT(id) DO UPDATE SET
+                     pc_id = excluded.pc_id, hours = excluded.hours, minutes = excluded.minutes,
+                     seconds = excluded.seconds, centiseconds = excluded.centiseconds, event_type = excluded.event_type,
+                     speed = excluded.speed, extra_value = excluded.extra_value, is_control_zone = excluded.is_control_zone,
+                     order_index = excluded.order_index, defined_by = excluded.defined_by,
+                     distance_meters = excluded.distance_meters, note = excluded.note, landmark = excluded.landmark",
+                rusqlite::params![
+                    reference.id,
+                    pc.id,
+                    reference.hours,
+                    reference.minutes,
+                    reference.seconds,
+                    reference.centiseconds,
+                    reference.event_type,
+                    reference.speed,
+                    reference.extra_value,
+                    reference.is_control_zone,
+                    reference.order_index,
+                    reference.defined_by,
+                    reference.distance_meters,
+                    reference.note,
+                    reference.landmark,
+                    reference.created_at
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    // Only tulips the edit changed are kept, so only those are written
+    for state in &to.pcs {
+        let previous = from.pcs.iter().find(|other| other.id == state.id);
+        for tulip in previous.map_or(&[][..], |other| &other.tulips) {
+            if !state.tulips.iter().any(|kept| kept.id == tulip.id) {
+                conn.execute("DELETE FROM reference_tulips WHERE id = ?1", [tulip.id])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        for tulip in &state.tulips {
+            conn.execute(
+                "DELETE FROM reference_tulips WHERE reference_id = ?1 AND id != ?2",
+                [tulip.reference_id, tulip.id],
+            )
+            .map_err(|e| e.to_string())?;
+            conn.execute(
+                "INSERT INTO reference_tulips (id, reference_id, mime_type, data, description, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(id) DO UPDATE SET
+                     reference_id = excluded.reference_id, mime_type = excluded.mime_type, data = excluded.data,
+                     description = excluded.description, created_at = excluded.created_at",
+                rusqlite::params![
+                    tulip.id,
+                    tulip.reference_id,
+                    tulip.mime_type,
+                    tulip.data,
+                    tulip.description,
+                    tulip.created_at
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+
+    for state in to.races.iter().filter(|state| state.race.is_none()) {
+        // Foreign key cascade will delete associated PCs and references
+        conn.execute("DELETE FROM races WHERE id = ?1", [state.id])
+            .map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM user_preferences WHERE substr(key, 1, length(?1)) = ?1",
+            [bundle::race_setting_prefix(state.id)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    for race in to.races.iter().filter(|state| state.race.is_some()) {
+        record_race_revision(conn, race.id, action)?;
+    }
+    for pc in &pcs {
+        record_pc_revision(conn, pc.id, action)?;
+    }
+    Ok(())
+}
+
+fn step_edit_history(conn: &mut Connection, undo: bool) -> Result<Option<EditHistoryEntry>, CommandError> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    // Undo takes the latest edit still done, redo the first one undone
+    let sql = if undo {
+        "SELECT id, before, after FROM edit_history WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+    } else {
+        "SELECT id, before, after FROM edit_history WHERE undone = 1 ORDER BY id ASC LIMIT 1"
+    };
+    let entry: Option<(i64, String, String)> = tx
+        .query_row(sql, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((id, before, after)) = entry else {
+        return Ok(None);
+    };
+
+    let before: EditState = serde_json::from_str(&before).map_err(|e| e.to_string())?;
+    let after: EditState = serde_json::from_str(&after).map_err(|e| e.to_string())?;
+    if undo {
+        restore_edit_state(&tx, &after, &before, "undo")?;
+    } else {
+        restore_edit_state(&tx, &before, &after, "redo")?;
+    }
+
+    tx.execute("UPDATE edit_history SET undone = ?1 WHERE id = ?2", rusqlite::params![undo, id])
+        .map_err(|e| e.to_string())?;
+    let entry = tx
+        .query_row(
+            &format!("SELECT {} FROM edit_history WHERE id = ?1", EDIT_HISTORY_COLUMNS),
+            [id],
+            edit_history_from_row,
+        )
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(Some(entry))
+}
+
+// Returns the edit undone, nothing when there is nothing left to undo
+#[tauri::command]
+pub fn undo_edit(db: State<Database>) -> Result<Option<EditHistoryEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    step_edit_history(&mut conn, true)
+}
+
+// Returns the edit redone, nothing when there is nothing left to redo
+#[tauri::command]
+pub fn redo_edit(db: State<Database>) -> Result<Option<EditHistoryEntry>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    step_edit_history(&mut conn, false)
+}
+
+// Newest first; undone edits are the ones redo brings back
+#[tauri::command]
+pub fn get_edit_history(db: State<Database>, limit: Option<i64>) -> Result<Vec<EditHistoryEntry>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM edit_history ORDER BY id DESC LIMIT ?1",
+            EDIT_HISTORY_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+
+    let entries = stmt
+        .query_map([limit.unwrap_or(EDIT_HISTORY_LIMIT)], edit_history_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(entries)
+}
+
+// ==================== TRASH COMMANDS ====================
+
+// Days a race or PC stays in the trash; 0 keeps it until the trash is emptied
+const TRASH_RETENTION_KEY: &str = "trash_retention_days";
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+fn trash_retention_days(conn: &Connection) -> Result<i64, String> {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM user_preferences WHERE key = ?1", [TRASH_RETENTION_KEY], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(value
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS))
+}
+
+// Permanently deletes what is in the trash, everything or only what was
+// deleted more than `older_than_days` ago. Returns how many races and PCs went.
+fn purge_trash(conn: &Connection, older_than_days: Option<i64>) -> Result<usize, String> {
+    let cutoff = older_than_days.map(|days| format!("-{} days", days));
+    let trashed = |sql: &str| -> Result<Vec<(i64, i64)>, String> {
+        let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map([&cutoff], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(ids)
+    };
+    let races = trashed(
+        "SELECT id, id FROM races
+         WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at <= datetime('now', ?1))",
+    )?;
+    let pcs = trashed(
+        "SELECT id, race_id FROM pcs
+         WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at <= datetime('now', ?1))",
+    )?;
+
+    // Undo history of what is purged could only bring back part of it
+    for (id, race_id) in &pcs {
+        // Foreign key cascade will delete associated references
+        conn.execute("DELETE FROM pcs WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        clear_edit_history(conn, *race_id)?;
+    }
+    for (id, _) in &races {
+        // Foreign key cascade will delete associated PCs and references
+        conn.execute("DELETE FROM races WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM user_preferences WHERE substr(key, 1, length(?1)) = ?1",
+            [bundle::race_setting_prefix(*id)],
+        )
+        .map_err(|e| e.to_string())?;
+        clear_edit_history(conn, *id)?;
+    }
+
+    Ok(races.len() + pcs.len())
+}
+
+// Purges what has been in the trash longer than the retention period
+pub fn purge_expired_trash(db: &Database) -> Result<usize, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let purged = match trash_retention_days(&tx)? {
+        0 => 0,
+        days => purge_trash(&tx, Some(days))?,
+    };
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(purged)
+}
+
+// Newest first
+#[tauri::command]
+pub fn get_trash(db: State<Database>) -> Result<Vec<TrashItem>, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let days = trash_retention_days(&conn)?;
+
     let mut stmt = conn
         .prepare(
-            "SELECT id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, created_at
-             FROM reference_entries WHERE id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    stmt.query_row([request.id], |row| {
-        Ok(ReferenceEntry {
-            id: row.get(0)?,
-            pc_id: row.get(1)?,
-            hours: row.get(2)?,
-            minutes: row.get(3)?,
-            seconds: row.get(4)?,
-            centiseconds: row.get(5)?,
-            event_type: row.get(6)?,
-            speed: row.get(7)?,
-            extra_value: row.get(8)?,
-            is_control_zone: row.get::<_, i32>(9)? != 0,
-            order_index: row.get(10)?,
-            created_at: row.get(11)?,
+            "SELECT kind, id, race_id, race_name, pc_number, pc_name, pc_count, reference_count, deleted_at,
+                    CASE WHEN ?1 > 0 THEN datetime(deleted_at, '+' || ?1 || ' days') END
+             FROM (
+                 SELECT 'race' AS kind, r.id, r.id AS race_id, r.name AS race_name, NULL AS pc_number, NULL AS pc_name,
+                        (SELECT COUNT(*) FROM pcs WHERE race_id = r.id AND deleted_at IS NULL) AS pc_count,
+                        (SELECT COUNT(*) FROM reference_entries e JOIN pcs p ON p.id = e.pc_id
+                         WHERE p.race_id = r.id AND p.deleted_at IS NULL) AS reference_count,
+                        r.deleted_at
+                 FROM races r WHERE r.deleted_at IS NOT NULL
+                 UNION ALL
+                 SELECT 'pc', p.id, p.race_id, r.name, p.pc_number, p.name, 1,
+                        (SELECT COUNT(*) FROM reference_entries WHERE pc_id = p.id),
+                        p.deleted_at
+                 FROM pcs p JOIN races r ON r.id = p.race_id
+                 WHERE p.deleted_at IS NOT NULL AND r.deleted_at IS NULL
+             )
+             ORDER BY deleted_at DESC, id DESC",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let items = stmt
+        .query_map([days], |row| {
+            Ok(TrashItem {
+                kind: row.get(0)?,
+                id: row.get(1)?,
+                race_id: row.get(2)?,
+                race_name: row.get(3)?,
+                pc_number: row.get(4)?,
+                pc_name: row.get(5)?,
+                pc_count: row.get(6)?,
+                reference_count: row.get(7)?,
+                deleted_at: row.get(8)?,
+                purge_at: row.get(9)?,
+            })
         })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(items)
+}
+
+// Brings a race back from the trash with all its PCs and references
+#[tauri::command]
+pub fn restore_race(db: State<Database>, id: i64) -> Result<Race, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if query_race(&tx, id)?.deleted_at.is_none() {
+        return Err(format!("Race {} is not in the trash", id).into());
+    }
+    ensure_race_unlocked(&tx, id)?;
+
+    let before = capture_edit(&tx, &[id], &[], false)?;
+    tx.execute("UPDATE races SET deleted_at = NULL WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    record_edit(&tx, "restore_race", before)?;
+    let race = query_race(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(race)
+}
+
+// Brings a PC back with its references. It keeps its number, shifting the
+// PCs that took it since one up. Returns the PCs of the race.
+#[tauri::command]
+pub fn restore_pc(db: State<Database>, id: i64) -> Result<Vec<PC>, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let pc = query_pc(&tx, id)?;
+    if pc.deleted_at.is_none() {
+        return Err(format!("PC {} is not in the trash", pc.pc_number).into());
+    }
+    if query_race(&tx, pc.race_id)?.deleted_at.is_some() {
+        return Err("The race of this PC is in the trash, restore the race".to_string().into());
+    }
+    ensure_race_unlocked(&tx, pc.race_id)?;
+
+    let mut pc_ids: Vec<i64> = query_pcs(&tx, pc.race_id)?.iter().map(|pc| pc.id).collect();
+    pc_ids.push(id);
+    let before = capture_edit(&tx, &[], &pc_ids, false)?;
+
+    let taken: bool = tx
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM pcs WHERE race_id = ?1 AND pc_number = ?2 AND deleted_at IS NULL)",
+            [pc.race_id, pc.pc_number as i64],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if taken {
+        tx.execute(
+            "UPDATE pcs SET pc_number = -(pc_number + 1) WHERE race_id = ?1 AND pc_number >= ?2 AND deleted_at IS NULL",
+            [pc.race_id, pc.pc_number as i64],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE pcs SET pc_number = -pc_number WHERE race_id = ?1 AND pc_number < 0 AND deleted_at IS NULL",
+            [pc.race_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute("UPDATE pcs SET deleted_at = NULL WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    record_edit(&tx, "restore_pc", before)?;
+
+    let pcs = query_pcs(&tx, pc.race_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(pcs)
+}
+
+// Deletes a race in the trash for good
+#[tauri::command]
+pub fn purge_race(db: State<Database>, id: i64) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if query_race(&tx, id)?.deleted_at.is_none() {
+        return Err(format!("Race {} is not in the trash", id));
+    }
+
+    // Foreign key cascade will delete associated PCs and references
+    tx.execute("DELETE FROM races WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM user_preferences WHERE substr(key, 1, length(?1)) = ?1",
+        [bundle::race_setting_prefix(id)],
+    )
+    .map_err(|e| e.to_string())?;
+    clear_edit_history(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// Deletes a PC in the trash for good
+#[tauri::command]
+pub fn purge_pc(db: State<Database>, id: i64) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let pc = query_pc(&tx, id)?;
+    if pc.deleted_at.is_none() {
+        return Err(format!("PC {} is not in the trash", pc.pc_number));
+    }
+
+    // Foreign key cascade will delete associated references
+    tx.execute("DELETE FROM pcs WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    clear_edit_history(&tx, pc.race_id)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// Returns how many races and PCs were deleted
+#[tauri::command]
+pub fn empty_trash(db: State<Database>) -> Result<usize, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let purged = purge_trash(&tx, None)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(purged)
+}
+
+#[tauri::command]
+pub fn get_trash_retention_days(db: State<Database>) -> Result<i64, String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    trash_retention_days(&conn)
+}
+
+// 0 keeps everything until the trash is emptied by hand
+#[tauri::command]
+pub fn set_trash_retention_days(db: State<Database>, days: i64) -> Result<(), String> {
+    if days < 0 {
+        return Err("The retention period cannot be negative".to_string());
+    }
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO user_preferences (key, value) VALUES (?1, ?2)",
+        [TRASH_RETENTION_KEY, &days.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// ==================== BULLETIN COMMANDS ====================
+
+const BULLETIN_COLUMNS: &str = "id, race_id, name, operations, status, applied_at, reverted_at, created_at";
+
+fn bulletin_from_row(row: &rusqlite::Row) -> rusqlite::Result<Bulletin> {
+    let name: String = row.get(2)?;
+    let operations: String = row.get(3)?;
+    // Corrupt operations must not load as an empty bulletin that applies as a no-op
+    let operations = serde_json::from_str(&operations).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(
+            3,
+            rusqlite::types::Type::Text,
+            format!("Bulletin \"{}\" has unreadable operations: {}", name, e).into(),
+        )
+    })?;
+    Ok(Bulletin {
+        id: row.get(0)?,
+        race_id: row.get(1)?,
+        name,
+        operations,
+        status: row.get(4)?,
+        applied_at: row.get(5)?,
+        reverted_at: row.get(6)?,
+        created_at: row.get(7)?,
     })
+}
+
+fn query_bulletin(conn: &Connection, id: i64) -> Result<Bulletin, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM bulletins WHERE id = ?1", BULLETIN_COLUMNS),
+        [id],
+        bulletin_from_row,
+    )
     .map_err(|e| e.to_string())
 }
 
+// Reference id at a 1-based position of the PC
+fn reference_at(ids: &[i64], pc_number: i32, position: usize) -> Result<i64, String> {
+    position
+        .checked_sub(1)
+        .and_then(|index| ids.get(index))
+        .copied()
+        .ok_or_else(|| format!("PC {} has no reference {}", pc_number, position))
+}
+
+fn reference_range(ids: &[i64], pc_number: i32, from: usize, to: usize) -> Result<Vec<i64>, String> {
+    if from > to {
+        return Err(format!("PC {}: invalid range {}-{}", pc_number, from, to));
+    }
+    reference_at(ids, pc_number, to)?;
+    (from..=to).map(|position| reference_at(ids, pc_number, position)).collect()
+}
+
+fn apply_bulletin_operation(conn: &Connection, pc_ids: &HashMap<i32, i64>, operation: &BulletinOperation) -> Result<(), String> {
+    let pc_number = operation.pc_number();
+    let pc_id = *pc_ids.get(&pc_number).ok_or_else(|| format!("PC {} not found", pc_number))?;
+    let ids = query_reference_ids(conn, pc_id)?;
+
+    match operation {
+        BulletinOperation::ShiftTimes { from, to, offset_centiseconds, .. } => {
+            // A distance-defined reference would get its time back from the distance
+            // on recompute, so the shifted ones become defined by their new time
+            for id in reference_range(&ids, pc_number, *from, *to)? {
+                let mut reference = query_reference(conn, id)?;
+                reference.set_time_centiseconds((reference.time_centiseconds() + offset_centiseconds).rem_euclid(24 * 360000));
+                conn.execute(
+                    "UPDATE reference_entries SET hours = ?1, minutes = ?2, seconds = ?3, centiseconds = ?4, defined_by = 'time' WHERE id = ?5",
+                    rusqlite::params![reference.hours, reference.minutes, reference.seconds, reference.centiseconds, id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        BulletinOperation::SetSpeed { position, speed, .. } => {
+            write_segment_speed(conn, reference_at(&ids, pc_number, *position)?, *speed)
+                .map_err(|e| format!("PC {}: {}", pc_number, e))?;
+        }
+        BulletinOperation::InsertReference { position, reference, .. } => {
+            if *position == 0 || *position > ids.len() + 1 {
+                return Err(format!("PC {} has no position {} to insert at", pc_number, position));
+            }
+            let request = CreateReferenceRequest { pc_id, ..reference.clone() };
+            let mut ids = ids;
+            let id = insert_reference(conn, &request, *position as i32 - 1)?;
+            ids.insert(position - 1, id);
+            write_reference_order(conn, &ids)?;
+        }
+        BulletinOperation::RemoveReference { position, .. } => {
+            let id = reference_at(&ids, pc_number, *position)?;
+            conn.execute("DELETE FROM reference_entries WHERE id = ?1", [id])
+                .map_err(|e| e.to_string())?;
+            write_reference_order(conn, &query_reference_ids(conn, pc_id)?)?;
+        }
+        BulletinOperation::SetControlZone { from, to, is_control_zone, .. } => {
+            for id in reference_range(&ids, pc_number, *from, *to)? {
+                conn.execute(
+                    "UPDATE reference_entries SET is_control_zone = ?1 WHERE id = ?2",
+                    rusqlite::params![is_control_zone, id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Runs every operation of the bulletin and reports what changed in each PC.
+// The caller decides whether to commit.
+fn run_bulletin(conn: &Connection, bulletin: &Bulletin) -> Result<(Vec<BulletinPCChange>, BulletinUndo), String> {
+    let mut pc_ids: HashMap<i32, i64> = HashMap::new();
+    let mut affected: Vec<i64> = Vec::new();
+    for operation in &bulletin.operations {
+        let pc_number = operation.pc_number();
+        let pc_id: i64 = conn
+            .query_row(
+                "SELECT id FROM pcs WHERE race_id = ?1 AND pc_number = ?2 AND deleted_at IS NULL",
+                rusqlite::params![bulletin.race_id, pc_number],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("PC {} not found", pc_number))?;
+        pc_ids.insert(pc_number, pc_id);
+        if !affected.contains(&pc_id) {
+            affected.push(pc_id);
+        }
+    }
+
+    let before = affected.iter().map(|&pc_id| pc_snapshot(conn, pc_id)).collect::<Result<Vec<_>, _>>()?;
+    let mut tulips = Vec::new();
+    for &pc_id in &affected {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM reference_tulips
+                 WHERE reference_id IN (SELECT id FROM reference_entries WHERE pc_id = ?1)",
+                TULIP_COLUMNS
+            ))
+            .map_err(|e| e.to_string())?;
+        let pc_tulips = stmt
+            .query_map([pc_id], tulip_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        tulips.extend(pc_tulips);
+    }
+
+    for (index, operation) in bulletin.operations.iter().enumerate() {
+        apply_bulletin_operation(conn, &pc_ids, operation).map_err(|e| format!("Change {}: {}", index + 1, e))?;
+    }
+
+    let mut changes = Vec::new();
+    let mut after = Vec::new();
+    for (pc_id, old) in affected.iter().zip(&before) {
+        recompute_pc_route(conn, *pc_id)?;
+        let new = pc_snapshot(conn, *pc_id)?;
+        changes.push(BulletinPCChange {
+            pc_id: *pc_id,
+            pc_number: new.pc.pc_number,
+            references: revisions::reference_changes(old, &new),
+            validation: validate_pc_references(conn, &new.pc)?,
+        });
+        after.push(new);
+    }
+
+    Ok((changes, BulletinUndo { before, after, tulips }))
+}
+
 #[tauri::command]
-pub fn delete_reference(db: State<Database>, id: i64) -> Result<(), String> {
+pub fn get_bulletins(db: State<Database>, race_id: i64) -> Result<Vec<Bulletin>, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM reference_entries WHERE id = ?1", [id])
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM bulletins WHERE race_id = ?1 ORDER BY id ASC",
+            BULLETIN_COLUMNS
+        ))
         .map_err(|e| e.to_string())?;
-    Ok(())
+
+    let bulletins = stmt
+        .query_map([race_id], bulletin_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(bulletins)
 }
 
+// Creates a draft, or edits one that has not been applied
 #[tauri::command]
-pub fn toggle_control_zone(db: State<Database>, id: i64) -> Result<ReferenceEntry, String> {
+pub fn save_bulletin(db: State<Database>, request: SaveBulletinRequest) -> Result<Bulletin, String> {
     let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let operations = serde_json::to_string(&request.operations).map_err(|e| e.to_string())?;
 
-    conn.execute(
-        "UPDATE reference_entries SET is_control_zone = NOT is_control_zone WHERE id = ?1",
+    let id = match request.id {
+        Some(id) => {
+            if query_bulletin(&conn, id)?.status != "draft" {
+                return Err("Only draft bulletins can be edited".to_string());
+            }
+            conn.execute(
+                "UPDATE bulletins SET name = ?1, operations = ?2 WHERE id = ?3",
+                rusqlite::params![request.name, operations, id],
+            )
+            .map_err(|e| e.to_string())?;
+            id
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO bulletins (race_id, name, operations) VALUES (?1, ?2, ?3)",
+                rusqlite::params![request.race_id, request.name, operations],
+            )
+            .map_err(|e| e.to_string())?;
+            conn.last_insert_rowid()
+        }
+    };
+
+    query_bulletin(&conn, id)
+}
+
+#[tauri::command]
+pub fn delete_bulletin(db: State<Database>, id: i64) -> Result<(), String> {
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    if query_bulletin(&conn, id)?.status == "applied" {
+        return Err("Revert the bulletin before deleting it".to_string());
+    }
+    conn.execute("DELETE FROM bulletins WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn preview_bulletin(db: State<Database>, id: i64) -> Result<BulletinPreview, String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    // Dropped without committing
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let bulletin = query_bulletin(&tx, id)?;
+    let (pcs, _) = run_bulletin(&tx, &bulletin)?;
+    Ok(BulletinPreview { bulletin, applied: false, pcs })
+}
+
+// Applies every change of the bulletin or none of them
+#[tauri::command]
+pub fn apply_bulletin(db: State<Database>, id: i64) -> Result<BulletinPreview, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let bulletin = query_bulletin(&tx, id)?;
+    ensure_race_unlocked(&tx, bulletin.race_id)?;
+    if bulletin.status == "applied" {
+        return Err(format!("Bulletin \"{}\" is already applied", bulletin.name).into());
+    }
+
+    let (pcs, undo) = run_bulletin(&tx, &bulletin)?;
+    for pc in &pcs {
+        record_pc_revision(&tx, pc.pc_id, "apply_bulletin")?;
+    }
+    clear_edit_history(&tx, bulletin.race_id)?;
+    tx.execute(
+        "UPDATE bulletins SET status = 'applied', undo = ?1, applied_at = datetime('now'), reverted_at = NULL WHERE id = ?2",
+        rusqlite::params![serde_json::to_string(&undo).map_err(|e| e.to_string())?, id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let bulletin = query_bulletin(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(BulletinPreview { bulletin, applied: true, pcs })
+}
+
+// Puts the affected PCs back as they were before the bulletin. PCs edited
+// after applying it are refused unless `force` is set, those edits are lost.
+#[tauri::command]
+pub fn revert_bulletin(db: State<Database>, id: i64, force: Option<bool>) -> Result<Bulletin, CommandError> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let bulletin = query_bulletin(&tx, id)?;
+    ensure_race_unlocked(&tx, bulletin.race_id)?;
+    if bulletin.status != "applied" {
+        return Err(format!("Bulletin \"{}\" is not applied", bulletin.name).into());
+    }
+    let undo: String = tx
+        .query_row("SELECT undo FROM bulletins WHERE id = ?1", [id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let undo: BulletinUndo = serde_json::from_str(&undo).map_err(|e| e.to_string())?;
+
+    for (before, after) in undo.before.iter().zip(&undo.after) {
+        let current = pc_snapshot(&tx, after.pc.id)
+            .map_err(|_| format!("PC {} no longer exists", after.pc.pc_number))?;
+        let edited = !revisions::field_changes(&after.pc, &current.pc).is_empty()
+            || !revisions::reference_changes(after, &current).is_empty();
+        if edited && !force.unwrap_or(false) {
+            return Err(format!("PC {} was edited after the bulletin was applied", current.pc.pc_number).into());
+        }
+        restore_pc_snapshot(&tx, before)?;
+    }
+
+    for tulip in &undo.tulips {
+        tx.execute(
+            "INSERT OR IGNORE INTO reference_tulips (reference_id, mime_type, data, description, created_at)
+             SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM reference_entries WHERE id = ?1)",
+            rusqlite::params![tulip.reference_id, tulip.mime_type, tulip.data, tulip.description, tulip.created_at],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    for before in &undo.before {
+        record_pc_revision(&tx, before.pc.id, "revert_bulletin")?;
+    }
+    clear_edit_history(&tx, bulletin.race_id)?;
+    tx.execute(
+        "UPDATE bulletins SET status = 'reverted', undo = NULL, reverted_at = datetime('now') WHERE id = ?1",
         [id],
     )
     .map_err(|e| e.to_string())?;
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, pc_id, hours, minutes, seconds, centiseconds, event_type, speed, extra_value, is_control_zone, order_index, created_at
-             FROM reference_entries WHERE id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-
-    stmt.query_row([id], |row| {
-        Ok(ReferenceEntry {
-            id: row.get(0)?,
-            pc_id: row.get(1)?,
-            hours: row.get(2)?,
-            minutes: row.get(3)?,
-            seconds: row.get(4)?,
-            centiseconds: row.get(5)?,
-            event_type: row.get(6)?,
-            speed: row.get(7)?,
-            extra_value: row.get(8)?,
-            is_control_zone: row.get::<_, i32>(9)? != 0,
-            order_index: row.get(10)?,
-            created_at: row.get(11)?,
-        })
-    })
-    .map_err(|e| e.to_string())
+    let bulletin = query_bulletin(&tx, id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(bulletin)
 }
 
 // ==================== PREFERENCE COMMANDS ====================
@@ -482,3 +3786,133 @@ pub fn set_preference(db: State<Database>, key: String, value: String) -> Result
     .map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tauri::Manager;
+
+    fn database(name: &str) -> Database {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        crate::database::prepare_schema(&conn).unwrap();
+        let backup_dir = std::env::temp_dir().join(format!("kiroshi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&backup_dir);
+        fs::create_dir_all(&backup_dir).unwrap();
+        Database { conn: std::sync::Mutex::new(conn), backup_dir }
+    }
+
+    fn reference(pc_id: i64, minutes: i32, event_type: &str) -> CreateReferenceRequest {
+        serde_json::from_value(json!({
+            "pc_id": pc_id, "hours": 10, "minutes": minutes, "seconds": 0, "centiseconds": 0,
+            "event_type": event_type, "speed": 45000, "extra_value": null
+        }))
+        .unwrap()
+    }
+
+    fn race(name: &str) -> CreateRaceRequest {
+        serde_json::from_value(json!({ "name": name })).unwrap()
+    }
+
+    #[test]
+    fn a_locked_race_refuses_every_roadbook_edit() {
+        let app = tauri::test::mock_app();
+        app.manage(database("lock"));
+        let db = || app.state::<Database>();
+        let path = |name: &str| db().backup_dir.join(name).to_string_lossy().to_string();
+
+        let locked = create_race(db(), race("Rally")).unwrap();
+        let pc = create_pc(db(), locked.id).unwrap();
+        let lar = create_reference(db(), reference(pc.id, 0, "LAR")).unwrap();
+        let r1 = create_reference(db(), reference(pc.id, 1, "REF")).unwrap();
+        set_reference_tulip(db(), r1.id, "image/png".to_string(), vec![1], None).unwrap();
+        let trashed_pc = create_pc(db(), locked.id).unwrap();
+        delete_pc(db(), trashed_pc.id).unwrap();
+        let trashed_race = create_race(db(), race("Trashed")).unwrap();
+        delete_race(db(), trashed_race.id).unwrap();
+        let other = create_race(db(), race("Other")).unwrap();
+        let other_pc = create_pc(db(), other.id).unwrap();
+
+        let operations = vec![BulletinOperation::SetControlZone { pc_number: 1, from: 1, to: 1, is_control_zone: true }];
+        let bulletin = |name: &str| SaveBulletinRequest { id: None, race_id: locked.id, name: name.to_string(), operations: operations.clone() };
+        let applied = save_bulletin(db(), bulletin("applied")).unwrap();
+        apply_bulletin(db(), applied.id).unwrap();
+        let draft = save_bulletin(db(), bulletin("draft")).unwrap();
+
+        let revision = get_revisions(db(), locked.id, Some(pc.id)).unwrap().pop().unwrap();
+        export_race_bundle(db(), locked.id, path("race.json")).unwrap();
+        export_roadbook_csv(db(), locked.id, None, path("race.csv")).unwrap();
+        let codes: Vec<String> = export_race_qr(db(), locked.id, None).unwrap().into_iter().map(|code| code.payload).collect();
+        let backup = create_backup(db()).unwrap();
+
+        // Leaves an edit of the race to undo and one to redo
+        toggle_control_zone(db(), r1.id).unwrap();
+        toggle_control_zone(db(), r1.id).unwrap();
+        undo_edit(db()).unwrap();
+
+        lock_race(db(), locked.id, None).unwrap();
+        lock_race(db(), trashed_race.id, None).unwrap();
+        let before = serde_json::to_value(get_references_by_pc(db(), pc.id).unwrap()).unwrap();
+
+        let update_race_request = serde_json::from_value(json!({ "id": locked.id, "name": "Renamed" })).unwrap();
+        let update_pc_request = serde_json::from_value(json!({ "id": pc.id, "pc_type": "regularity" })).unwrap();
+        let update_reference_request = serde_json::from_value(json!({
+            "id": r1.id, "hours": 10, "minutes": 2, "seconds": 0, "centiseconds": 0,
+            "event_type": "REF", "speed": 45000, "extra_value": null
+        }))
+        .unwrap();
+
+        let results: Vec<(&str, Result<(), CommandError>)> = vec![
+            ("update_race", update_race(db(), update_race_request).map(drop)),
+            ("delete_race", delete_race(db(), locked.id)),
+            ("create_pc", create_pc(db(), locked.id).map(drop)),
+            ("update_pc", update_pc(db(), update_pc_request).map(drop)),
+            ("delete_pc", delete_pc(db(), pc.id)),
+            ("create_next_pc", create_next_pc(db(), pc.id).map(drop)),
+            ("insert_pc_at", insert_pc_at(db(), locked.id, 1).map(drop)),
+            ("renumber_pcs", renumber_pcs(db(), locked.id).map(drop)),
+            ("move_pc", move_pc(db(), pc.id, 1).map(drop)),
+            ("duplicate_pc", duplicate_pc(db(), other_pc.id, Some(locked.id), None).map(drop)),
+            ("create_reference", create_reference(db(), reference(pc.id, 2, "REF")).map(drop)),
+            ("update_reference", update_reference(db(), update_reference_request).map(drop)),
+            ("set_segment_speed", set_segment_speed(db(), lar.id, 40000).map(drop)),
+            ("delete_reference", delete_reference(db(), r1.id)),
+            ("toggle_control_zone", toggle_control_zone(db(), r1.id).map(drop)),
+            ("set_reference_tulip", set_reference_tulip(db(), r1.id, "image/png".to_string(), vec![2], None).map(drop)),
+            ("delete_reference_tulip", delete_reference_tulip(db(), r1.id)),
+            ("insert_reference_at", insert_reference_at(db(), reference(pc.id, 2, "REF"), 1).map(drop)),
+            ("move_reference", move_reference(db(), r1.id, 0).map(drop)),
+            ("move_reference_up", move_reference_up(db(), r1.id).map(drop)),
+            ("move_reference_down", move_reference_down(db(), lar.id).map(drop)),
+            ("compact_reference_order", compact_reference_order(db(), pc.id).map(drop)),
+            ("import_roadbook_csv", import_roadbook_csv(db(), locked.id, path("race.csv"), None, Some(true)).map(drop)),
+            ("import_roadbook_xlsx", import_roadbook_xlsx(db(), locked.id, path("race.xlsx"), XlsxImportOptions::default()).map(drop)),
+            ("import_pdf_references", import_pdf_references(db(), locked.id, path("race.pdf"), PdfImportOptions::default()).map(drop)),
+            ("import_race_bundle", import_race_bundle(db(), path("race.json"), Some("replace".to_string())).map(drop)),
+            ("import_race_qr", import_race_qr(db(), codes, Some("merge".to_string())).map(drop)),
+            ("extract_backup_race", extract_backup_race(db(), backup.file_name.clone(), locked.id, Some("replace".to_string())).map(drop)),
+            ("restore_backup", restore_backup(db(), backup.file_name)),
+            ("restore_revision", restore_revision(db(), revision.id).map(drop)),
+            ("undo_edit", undo_edit(db()).map(drop)),
+            ("redo_edit", redo_edit(db()).map(drop)),
+            ("restore_pc", restore_pc(db(), trashed_pc.id).map(drop)),
+            ("restore_race", restore_race(db(), trashed_race.id).map(drop)),
+            ("apply_bulletin", apply_bulletin(db(), draft.id).map(drop)),
+            ("revert_bulletin", revert_bulletin(db(), applied.id, Some(true)).map(drop)),
+        ];
+
+        for (command, result) in results {
+            assert!(matches!(result, Err(CommandError::RaceLocked(_))), "{} was not refused: {:?}", command, result);
+        }
+        assert_eq!(serde_json::to_value(get_references_by_pc(db(), pc.id).unwrap()).unwrap(), before);
+        assert_eq!(get_pcs_by_race(db(), locked.id).unwrap().len(), 1);
+        assert_eq!(get_race(db(), locked.id).unwrap().name, "Rally");
+        // Refused before any backup was taken
+        let reasons: Vec<String> = get_backups(db()).unwrap().into_iter().map(|b| b.reason).collect();
+        assert!(!reasons.iter().any(|reason| matches!(reason.as_str(), "before_import" | "before_restore")), "{:?}", reasons);
+
+        // Other races stay editable
+        assert!(create_pc(db(), other.id).is_ok());
+    }
+}
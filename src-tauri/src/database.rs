@@ -1,12 +1,14 @@
+use crate::backup;
 use rusqlite::{Connection, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use tauri::AppHandle;
 use tauri::Manager;
 
 pub struct Database {
     pub conn: Mutex<Connection>,
+    pub backup_dir: PathBuf,
 }
 
 pub fn get_db_path(app: &AppHandle) -> PathBuf {
@@ -18,10 +20,43 @@ pub fn get_db_path(app: &AppHandle) -> PathBuf {
     app_data_dir.join("kiroshi.db")
 }
 
-pub fn initialize(app: &AppHandle) -> Result<()> {
+pub fn get_backup_dir(app: &AppHandle) -> PathBuf {
+    get_db_path(app).with_file_name("backups")
+}
+
+// Foreign keys are a setting of each connection, not of the database file,
+// so every connection has to turn them on
+pub fn open(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
+}
+
+pub fn initialize(app: &AppHandle) -> std::result::Result<(), String> {
     let db_path = get_db_path(app);
-    let conn = Connection::open(&db_path)?;
+    let backup_dir = get_backup_dir(app);
+    let existed = db_path.exists();
+    let conn = open(&db_path).map_err(|e| e.to_string())?;
+
+    // Keep the database as the previous version left it
+    if existed && schema_version(&conn).map_err(|e| e.to_string())? < MIGRATIONS.len() {
+        backup::create(&conn, &backup_dir, backup::BEFORE_MIGRATION)?;
+    }
+    prepare_schema(&conn).map_err(|e| e.to_string())?;
+    backup::create(&conn, &backup_dir, backup::STARTUP)?;
+
+    // Store connection in app state
+    app.manage(Database {
+        conn: Mutex::new(conn),
+        backup_dir,
+    });
+
+    Ok(())
+}
 
+// Creates the tables and brings them up to date. Also used on backups, which
+// may have been taken by an older version.
+pub fn prepare_schema(conn: &Connection) -> Result<()> {
     // Create tables
     conn.execute_batch(
         "
@@ -65,9 +100,6 @@ pub fn initialize(app: &AppHandle) -> Result<()> {
             value TEXT NOT NULL
         );
 
-        -- Enable foreign key support
-        PRAGMA foreign_keys = ON;
-
         -- Indexes for performance
         CREATE INDEX IF NOT EXISTS idx_pcs_race_id ON pcs(race_id);
         CREATE INDEX IF NOT EXISTS idx_references_pc_id ON reference_entries(pc_id);
@@ -75,10 +107,191 @@ pub fn initialize(app: &AppHandle) -> Result<()> {
         ",
     )?;
 
-    // Store connection in app state
-    app.manage(Database {
-        conn: Mutex::new(conn),
-    });
+    run_migrations(conn)
+}
+
+// Schema migrations applied on top of the base tables, tracked through
+// `PRAGMA user_version`. Each entry runs once, in order, inside a transaction.
+const MIGRATIONS: &[&str] = &[
+    // 1: speeds are stored as thousandths of km/h instead of whole km/h
+    "UPDATE reference_entries SET speed = speed * 1000;",
+    // 2: race metadata
+    "
+    ALTER TABLE races ADD COLUMN event_date TEXT;
+    ALTER TABLE races ADD COLUMN event_end_date TEXT;
+    ALTER TABLE races ADD COLUMN location TEXT;
+    ALTER TABLE races ADD COLUMN organizer TEXT;
+    ALTER TABLE races ADD COLUMN category TEXT;
+    ALTER TABLE races ADD COLUMN car_number TEXT;
+    ALTER TABLE races ADD COLUMN driver_name TEXT;
+    ALTER TABLE races ADD COLUMN navigator_name TEXT;
+    ALTER TABLE races ADD COLUMN notes TEXT;
+    CREATE INDEX IF NOT EXISTS idx_races_event_date ON races(event_date);
+    ",
+    // 3: PC metadata
+    "
+    ALTER TABLE pcs ADD COLUMN name TEXT;
+    ALTER TABLE pcs ADD COLUMN pc_type TEXT NOT NULL DEFAULT 'regularity'
+        CHECK(pc_type IN ('regularity', 'liaison', 'super_special'));
+    ALTER TABLE pcs ADD COLUMN start_location TEXT;
+    ALTER TABLE pcs ADD COLUMN scheduled_start_centiseconds INTEGER;
+    ALTER TABLE pcs ADD COLUMN target_time_centiseconds INTEGER;
+    ALTER TABLE pcs ADD COLUMN total_distance_meters INTEGER;
+    ALTER TABLE pcs ADD COLUMN notes TEXT;
+    ",
+    // 4: reference notes, landmarks and tulip images
+    "
+    ALTER TABLE reference_entries ADD COLUMN note TEXT;
+    ALTER TABLE reference_entries ADD COLUMN landmark TEXT;
+    CREATE TABLE IF NOT EXISTS reference_tulips (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        reference_id INTEGER NOT NULL UNIQUE,
+        mime_type TEXT NOT NULL,
+        data BLOB NOT NULL,
+        description TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        FOREIGN KEY (reference_id) REFERENCES reference_entries(id) ON DELETE CASCADE
+    );
+    ",
+    // 5: references defined by distance from the start
+    "
+    ALTER TABLE reference_entries ADD COLUMN defined_by TEXT NOT NULL DEFAULT 'time'
+        CHECK(defined_by IN ('time', 'distance'));
+    ALTER TABLE reference_entries ADD COLUMN distance_meters REAL;
+    ",
+    // 6: stable race identity for bundles moved between machines
+    "
+    ALTER TABLE races ADD COLUMN uid TEXT;
+    UPDATE races SET uid = lower(hex(randomblob(16)));
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_races_uid ON races(uid);
+    CREATE TRIGGER IF NOT EXISTS races_assign_uid AFTER INSERT ON races
+    WHEN NEW.uid IS NULL
+    BEGIN
+        UPDATE races SET uid = lower(hex(randomblob(16))) WHERE id = NEW.id;
+    END;
+    ",
+    // 7: spreadsheet column mappings saved per organiser
+    "
+    CREATE TABLE IF NOT EXISTS import_templates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        organizer TEXT,
+        sheet_name TEXT,
+        header_row INTEGER,
+        mapping TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    ",
+    // 8: revision history, a JSON snapshot of the race or PC after each change
+    "
+    CREATE TABLE IF NOT EXISTS revisions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        race_id INTEGER NOT NULL,
+        pc_id INTEGER,
+        action TEXT NOT NULL,
+        snapshot TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        FOREIGN KEY (race_id) REFERENCES races(id) ON DELETE CASCADE,
+        FOREIGN KEY (pc_id) REFERENCES pcs(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_revisions_race ON revisions(race_id, pc_id);
+    ",
+    // 9: organiser bulletins, stored as a list of operations plus what undoes them
+    "
+    CREATE TABLE IF NOT EXISTS bulletins (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        race_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        operations TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'draft' CHECK(status IN ('draft', 'applied', 'reverted')),
+        undo TEXT,
+        applied_at TEXT,
+        reverted_at TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        FOREIGN KEY (race_id) REFERENCES races(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_bulletins_race ON bulletins(race_id);
+    ",
+    // 10: race lock during competition, with a log of every lock and unlock
+    "
+    ALTER TABLE races ADD COLUMN locked_at TEXT;
+    CREATE TABLE IF NOT EXISTS race_lock_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        race_id INTEGER NOT NULL,
+        action TEXT NOT NULL CHECK(action IN ('lock', 'unlock')),
+        reason TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        FOREIGN KEY (race_id) REFERENCES races(id) ON DELETE CASCADE
+    );
+    ",
+    // 11: undo and redo of roadbook edits, with the rows as they were before and after.
+    // No foreign key: entries must outlive the race they undo the deletion of.
+    "
+    CREATE TABLE IF NOT EXISTS edit_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        action TEXT NOT NULL,
+        race_id INTEGER,
+        before TEXT NOT NULL,
+        after TEXT NOT NULL,
+        undone INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    ",
+    // 12: trash bin. Races and PCs are marked deleted and purged later; the
+    // pcs table is rebuilt so PC numbers are only unique among live PCs.
+    "
+    ALTER TABLE races ADD COLUMN deleted_at TEXT;
+    CREATE TABLE pcs_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        race_id INTEGER NOT NULL,
+        pc_number INTEGER NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        name TEXT,
+        pc_type TEXT NOT NULL DEFAULT 'regularity'
+            CHECK(pc_type IN ('regularity', 'liaison', 'super_special')),
+        start_location TEXT,
+        scheduled_start_centiseconds INTEGER,
+        target_time_centiseconds INTEGER,
+        total_distance_meters INTEGER,
+        notes TEXT,
+        deleted_at TEXT,
+        FOREIGN KEY (race_id) REFERENCES races(id) ON DELETE CASCADE
+    );
+    INSERT INTO pcs_new (id, race_id, pc_number, created_at, name, pc_type, start_location,
+                         scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes)
+    SELECT id, race_id, pc_number, created_at, name, pc_type, start_location,
+           scheduled_start_centiseconds, target_time_centiseconds, total_distance_meters, notes
+    FROM pcs;
+    DELETE FROM sqlite_sequence WHERE name = 'pcs_new';
+    INSERT INTO sqlite_sequence (name, seq) SELECT 'pcs_new', seq FROM sqlite_sequence WHERE name = 'pcs';
+    DROP TABLE pcs;
+    ALTER TABLE pcs_new RENAME TO pcs;
+    CREATE INDEX IF NOT EXISTS idx_pcs_race_id ON pcs(race_id);
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_pcs_live_number ON pcs(race_id, pc_number) WHERE deleted_at IS NULL;
+    CREATE INDEX IF NOT EXISTS idx_races_deleted ON races(deleted_at);
+    ",
+];
+
+fn schema_version(conn: &Connection) -> Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// Foreign keys are off while migrating, so rebuilding a table does not
+// cascade into the rows that point at it
+fn run_migrations(conn: &Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    conn.pragma_update(None, "foreign_keys", false)?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    conn.pragma_update(None, "foreign_keys", true)?;
 
     Ok(())
 }
@@ -0,0 +1,35 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+// Errors the frontend needs to tell apart. They reach it as
+// `{ kind, message }`, everything else keeps travelling as a plain message.
+#[derive(Debug, thiserror::Error)]
+pub enum CommandError {
+    #[error("The race \"{0}\" is locked, unlock it to edit its roadbook")]
+    RaceLocked(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CommandError {
+    fn kind(&self) -> &'static str {
+        match self {
+            CommandError::RaceLocked(_) => "race_locked",
+            CommandError::Other(_) => "other",
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Other(message)
+    }
+}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CommandError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
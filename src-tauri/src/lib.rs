@@ -1,7 +1,21 @@
+mod backup;
+mod bundle;
+mod checksum;
 mod commands;
 mod database;
+mod error;
+mod march_table;
 mod models;
+mod pdf_import;
+mod qr_transfer;
 mod race_timer;
+mod revisions;
+mod roadbook;
+mod route;
+#[cfg(test)]
+mod test_support;
+mod validation;
+mod xlsx;
 
 use commands::*;
 use race_timer::*;
@@ -13,6 +27,10 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
             database::initialize(app.handle())?;
+            recompute_stale_routes(&app.state::<database::Database>())?;
+            record_initial_revisions(&app.state::<database::Database>())?;
+            purge_expired_trash(&app.state::<database::Database>())?;
+            backup::start_periodic_thread(app.handle().clone());
 
             // Initialize race timer and start background thread
             let timer = RaceTimer::new();
@@ -28,19 +46,110 @@ pub fn run() {
             create_race,
             update_race,
             delete_race,
+            // Race lock commands
+            lock_race,
+            unlock_race,
+            get_race_lock_events,
             // PC commands
             get_pcs_by_race,
             get_pc,
             create_pc,
+            update_pc,
             delete_pc,
             get_next_pc,
             create_next_pc,
+            // PC numbering commands
+            insert_pc_at,
+            renumber_pcs,
+            move_pc,
             // Reference commands
             get_references_by_pc,
             create_reference,
             update_reference,
+            set_segment_speed,
             delete_reference,
             toggle_control_zone,
+            // Tulip commands
+            get_reference_tulip,
+            set_reference_tulip,
+            delete_reference_tulip,
+            // Reference order commands
+            insert_reference_at,
+            move_reference,
+            move_reference_up,
+            move_reference_down,
+            compact_reference_order,
+            // Duplicate commands
+            duplicate_race,
+            duplicate_pc,
+            // Route commands
+            get_route_intervals,
+            // March table commands
+            get_march_table,
+            export_march_table_csv,
+            export_march_table_pdf,
+            // Validation commands
+            validate_pc,
+            validate_race,
+            // Checksum commands
+            get_race_checksum,
+            get_pc_checksum,
+            compare_race_checksum,
+            // Roadbook commands
+            export_roadbook_csv,
+            preview_roadbook_import,
+            import_roadbook_csv,
+            // XLSX import commands
+            get_xlsx_sheets,
+            preview_xlsx_import,
+            import_roadbook_xlsx,
+            get_import_templates,
+            save_import_template,
+            delete_import_template,
+            // PDF import commands
+            preview_pdf_import,
+            import_pdf_references,
+            // Bundle commands
+            export_race_bundle,
+            import_race_bundle,
+            // QR transfer commands
+            export_race_qr,
+            read_race_qr,
+            import_race_qr,
+            // Backup commands
+            create_backup,
+            get_backups,
+            get_backup_races,
+            restore_backup,
+            extract_backup_race,
+            // Integrity commands
+            check_database,
+            repair_database,
+            // Revision commands
+            get_revisions,
+            get_pc_revision,
+            diff_pc_revisions,
+            restore_revision,
+            // Undo commands
+            undo_edit,
+            redo_edit,
+            get_edit_history,
+            // Trash commands
+            get_trash,
+            restore_race,
+            restore_pc,
+            purge_race,
+            purge_pc,
+            empty_trash,
+            get_trash_retention_days,
+            set_trash_retention_days,
+            // Bulletin commands
+            get_bulletins,
+            save_bulletin,
+            delete_bulletin,
+            preview_bulletin,
+            apply_bulletin,
+            revert_bulletin,
             // Preference commands
             get_preference,
             set_preference,
@@ -58,6 +167,7 @@ pub fn run() {
             full_reset_race_timer,
             get_race_timer_state,
             set_race_clock_start,
+            set_race_stage,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
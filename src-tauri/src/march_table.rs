@@ -0,0 +1,344 @@
+use crate::models::{MarchRow, MarchTableOptions, ReferenceEntry};
+use crate::route::{self, RoutePoint};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+
+// "Tabla de marcha": passage time and distance at a fixed step along the
+// compiled route of a PC, with the references interleaved.
+
+// Keeps a mistyped step from producing a table nobody can print
+const MAX_ROWS: usize = 20000;
+
+fn step_mark(row_time: i64, row_distance: f64, previous: &RoutePoint) -> MarchRow {
+    MarchRow {
+        time_centiseconds: row_time,
+        distance_meters: row_distance,
+        speed: previous.speed,
+        reference_id: None,
+        event_type: None,
+        landmark: None,
+        is_speed_change: false,
+    }
+}
+
+fn reference_row(reference: &ReferenceEntry, point: &RoutePoint) -> MarchRow {
+    MarchRow {
+        time_centiseconds: point.time_centiseconds,
+        distance_meters: point.distance_meters,
+        speed: point.speed,
+        reference_id: Some(reference.id),
+        event_type: Some(reference.event_type.clone()),
+        landmark: reference.landmark.clone(),
+        is_speed_change: reference.is_speed_change(),
+    }
+}
+
+pub fn build(references: &[ReferenceEntry], options: &MarchTableOptions) -> Result<Vec<MarchRow>, String> {
+    let by_time = match options.step_by.as_str() {
+        "distance" => false,
+        "time" => true,
+        other => return Err(format!("Unknown march table step: {}", other)),
+    };
+    if options.step.is_nan() || options.step <= 0.0 {
+        return Err("The march table step must be greater than zero".to_string());
+    }
+
+    let points = route::compile(references);
+    let Some(first) = points.first() else {
+        return Ok(Vec::new());
+    };
+
+    // Step in meters or centiseconds, marks are counted from the LAR
+    let step = if by_time { options.step * 100.0 } else { options.step };
+    let origin = if by_time { first.time_centiseconds as f64 } else { 0.0 };
+    let mut mark = 1;
+
+    let mut rows = vec![reference_row(&references[0], first)];
+    for (index, point) in points.iter().enumerate().skip(1) {
+        let previous = &points[index - 1];
+        let rate = previous.speed as f64 / 360000.0; // meters per centisecond
+
+        loop {
+            let position = origin + mark as f64 * step;
+            let (start, end) = if by_time {
+                (previous.time_centiseconds as f64, point.time_centiseconds as f64)
+            } else {
+                (previous.distance_meters, point.distance_meters)
+            };
+            if position <= start {
+                mark += 1;
+                continue;
+            }
+            if position >= end {
+                break;
+            }
+
+            // Clamped to the reference, the clock may jump there on an ADL or ATR
+            let row = if by_time {
+                let distance = previous.distance_meters + (position - start) * rate;
+                step_mark(position.round() as i64, distance.min(point.distance_meters), previous)
+            } else {
+                let time = previous.time_centiseconds + ((position - start) / rate).round() as i64;
+                step_mark(time.min(point.time_centiseconds), position, previous)
+            };
+            rows.push(row);
+            mark += 1;
+
+            if rows.len() > MAX_ROWS {
+                return Err(format!("The march table would have more than {} rows, use a larger step", MAX_ROWS));
+            }
+        }
+
+        rows.push(reference_row(&references[index], point));
+    }
+
+    Ok(rows)
+}
+
+pub fn format_time(centiseconds: i64) -> String {
+    let total = centiseconds.rem_euclid(24 * 360000);
+    format!(
+        "{:02}:{:02}:{:02}.{:02}",
+        total / 360000,
+        total % 360000 / 6000,
+        total % 6000 / 100,
+        total % 100
+    )
+}
+
+fn format_km(meters: f64) -> String {
+    format!("{:.3}", meters / 1000.0)
+}
+
+pub fn format_speed(speed: i64) -> String {
+    let formatted = format!("{:.3}", speed as f64 / 1000.0);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+pub fn write_csv(rows: &[MarchRow], path: &str) -> Result<(), String> {
+    let mut writer = csv::Writer::from_path(path).map_err(|e| e.to_string())?;
+
+    writer
+        .write_record(["Hora", "Km", "Velocidad", "Evento", "Cambio de velocidad", "Punto de referencia"])
+        .map_err(|e| e.to_string())?;
+
+    for row in rows {
+        writer
+            .write_record([
+                format_time(row.time_centiseconds),
+                format_km(row.distance_meters),
+                format_speed(row.speed),
+                row.event_type.clone().unwrap_or_default(),
+                if row.is_speed_change { format_speed(row.speed) } else { String::new() },
+                row.landmark.clone().unwrap_or_default(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+
+    writer.flush().map_err(|e| e.to_string())
+}
+
+// The standard PDF fonts are WinAnsi encoded, which covers Spanish text
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            code @ (0x20..=0x7E | 0xA0..=0xFF) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+const ROW_HEIGHT: f32 = 14.0;
+const HEADER_HEIGHT: f32 = 60.0;
+const COLUMNS: [(&str, f32); 5] = [
+    ("Hora", 0.0),
+    ("Km", 90.0),
+    ("Vel", 150.0),
+    ("Evento", 205.0),
+    ("Punto de referencia", 265.0),
+];
+
+// Describes the step for the table header ("Cada 100 m", "Cada 10 s")
+pub fn describe_step(options: &MarchTableOptions) -> String {
+    let unit = if options.step_by == "time" { "s" } else { "m" };
+    format!("Cada {} {}", options.step, unit)
+}
+
+pub fn write_pdf(title: &str, subtitle: &str, rows: &[MarchRow], path: &str) -> Result<(), String> {
+    let rows_per_page = ((PAGE_HEIGHT - 2.0 * MARGIN - HEADER_HEIGHT) / ROW_HEIGHT) as usize;
+    let pages: Vec<&[MarchRow]> = if rows.is_empty() {
+        vec![&[]]
+    } else {
+        rows.chunks(rows_per_page).collect()
+    };
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let bold_font_id = Ref::new(4);
+    let page_ids: Vec<Ref> = (0..pages.len()).map(|i| Ref::new(5 + 2 * i as i32)).collect();
+    let regular = Name(b"F1");
+    let bold = Name(b"F2");
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_font_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    for (page_index, page_rows) in pages.iter().enumerate() {
+        let page_id = page_ids[page_index];
+        let content_id = Ref::new(page_id.get() + 1);
+
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        resources.fonts().pair(regular, font_id).pair(bold, bold_font_id);
+        resources.finish();
+        page.finish();
+
+        let mut content = Content::new();
+        let text = |content: &mut Content, font: Name, size: f32, x: f32, y: f32, value: &str| {
+            content.begin_text();
+            content.set_font(font, size);
+            content.next_line(x, y);
+            content.show(Str(&win_ansi(value)));
+            content.end_text();
+        };
+
+        // Title, page number and column headers
+        let top = PAGE_HEIGHT - MARGIN;
+        text(&mut content, bold, 14.0, MARGIN, top - 14.0, title);
+        text(&mut content, regular, 10.0, MARGIN, top - 30.0, subtitle);
+        let page_label = format!("{}/{}", page_index + 1, pages.len());
+        text(&mut content, regular, 10.0, PAGE_WIDTH - MARGIN - 30.0, top - 14.0, &page_label);
+        let header_y = top - HEADER_HEIGHT + 6.0;
+        for (label, x) in COLUMNS {
+            text(&mut content, bold, 10.0, MARGIN + x, header_y, label);
+        }
+        content.set_line_width(0.5);
+        content.move_to(MARGIN, header_y - 4.0);
+        content.line_to(PAGE_WIDTH - MARGIN, header_y - 4.0);
+        content.stroke();
+
+        for (row_index, row) in page_rows.iter().enumerate() {
+            let y = header_y - ROW_HEIGHT * (row_index as f32 + 1.0);
+
+            // Speed changes are banded so they stand out when printed
+            if row.is_speed_change {
+                content.set_fill_gray(0.85);
+                content.rect(MARGIN - 2.0, y - 3.0, PAGE_WIDTH - 2.0 * MARGIN + 4.0, ROW_HEIGHT);
+                content.fill_nonzero();
+                content.set_fill_gray(0.0);
+            }
+
+            let font = if row.reference_id.is_some() { bold } else { regular };
+            let event = match (&row.event_type, row.is_speed_change) {
+                (Some(event), true) => format!("{} > {}", event, format_speed(row.speed)),
+                (Some(event), false) => event.clone(),
+                (None, _) => String::new(),
+            };
+            let values = [
+                format_time(row.time_centiseconds),
+                format_km(row.distance_meters),
+                format_speed(row.speed),
+                event,
+                row.landmark.clone().unwrap_or_default(),
+            ];
+            for ((_, x), value) in COLUMNS.iter().zip(values) {
+                text(&mut content, font, 9.0, MARGIN + x, y, &value);
+            }
+        }
+
+        pdf.stream(content_id, &content.finish());
+    }
+
+    std::fs::write(path, pdf.finish()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{reference, TEN};
+
+    fn options(step_by: &str, step: f64) -> MarchTableOptions {
+        MarchTableOptions { step_by: step_by.to_string(), step }
+    }
+
+    // LAR at 0 m, REF at 600 m, CVT to 72 km/h at 1200 m, REF at 2000 m
+    fn route() -> Vec<ReferenceEntry> {
+        vec![
+            reference(1, "LAR", TEN, 36000),
+            reference(2, "REF", TEN + 6000, 36000),
+            reference(3, "CVT", TEN + 12000, 72000),
+            reference(4, "REF", TEN + 16000, 72000),
+        ]
+    }
+
+    fn summary(rows: &[MarchRow]) -> Vec<(Option<i64>, i64, f64)> {
+        rows.iter().map(|row| (row.reference_id, row.time_centiseconds - TEN, row.distance_meters)).collect()
+    }
+
+    #[test]
+    fn marks_on_a_reference_give_way_to_it() {
+        let rows = build(&route(), &options("distance", 300.0)).unwrap();
+        assert_eq!(
+            summary(&rows),
+            vec![
+                (Some(1), 0, 0.0),
+                (None, 3000, 300.0),
+                (Some(2), 6000, 600.0),
+                (None, 9000, 900.0),
+                (Some(3), 12000, 1200.0),
+                // 72 km/h after the CVT
+                (None, 13500, 1500.0),
+                (None, 15000, 1800.0),
+                (Some(4), 16000, 2000.0),
+            ]
+        );
+        assert_eq!(rows[5].speed, 72000);
+        assert!(rows[4].is_speed_change && !rows[5].is_speed_change);
+    }
+
+    #[test]
+    fn marks_count_from_the_lar_across_references() {
+        let rows = build(&route(), &options("distance", 500.0)).unwrap();
+        let marks: Vec<f64> = rows.iter().filter(|row| row.reference_id.is_none()).map(|row| row.distance_meters).collect();
+        assert_eq!(marks, vec![500.0, 1000.0, 1500.0]);
+    }
+
+    #[test]
+    fn time_steps() {
+        let rows = build(&route(), &options("time", 45.0)).unwrap();
+        assert_eq!(
+            summary(&rows),
+            vec![
+                (Some(1), 0, 0.0),
+                (None, 4500, 450.0),
+                (Some(2), 6000, 600.0),
+                (None, 9000, 900.0),
+                (Some(3), 12000, 1200.0),
+                (None, 13500, 1500.0),
+                (Some(4), 16000, 2000.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_steps_are_rejected() {
+        assert!(build(&route(), &options("laps", 100.0)).is_err());
+        assert!(build(&route(), &options("distance", 0.0)).is_err());
+        assert!(build(&route(), &options("distance", -100.0)).is_err());
+        assert!(build(&route(), &options("time", f64::NAN)).is_err());
+        assert!(build(&route(), &options("distance", 0.05)).is_err());
+        assert!(build(&[], &options("distance", 100.0)).unwrap().is_empty());
+    }
+}
@@ -1,20 +1,100 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Race {
     pub id: i64,
+    // Stable identity shared by every copy of the race imported from a bundle
+    pub uid: String,
     pub name: String,
+    pub event_date: Option<String>,     // YYYY-MM-DD
+    pub event_end_date: Option<String>, // YYYY-MM-DD, for multi-day events
+    pub location: Option<String>,
+    pub organizer: Option<String>,
+    pub category: Option<String>,
+    pub car_number: Option<String>,
+    pub driver_name: Option<String>,
+    pub navigator_name: Option<String>,
+    pub notes: Option<String>,
+    // Set while the roadbook is locked for competition
+    #[serde(default)]
+    pub locked_at: Option<String>,
+    // Set while the race is in the trash
+    #[serde(default)]
+    pub deleted_at: Option<String>,
     pub created_at: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaceLockEvent {
+    pub id: i64,
+    pub race_id: i64,
+    pub action: String, // "lock" or "unlock"
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRaceRequest {
+    pub name: String,
+    pub event_date: Option<String>,
+    pub event_end_date: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Option<String>,
+    pub category: Option<String>,
+    pub car_number: Option<String>,
+    pub driver_name: Option<String>,
+    pub navigator_name: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRaceRequest {
+    pub id: i64,
+    pub name: String,
+    pub event_date: Option<String>,
+    pub event_end_date: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Option<String>,
+    pub category: Option<String>,
+    pub car_number: Option<String>,
+    pub driver_name: Option<String>,
+    pub navigator_name: Option<String>,
+    pub notes: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PC {
     pub id: i64,
     pub race_id: i64,
     pub pc_number: i32,
+    pub name: Option<String>,
+    pub pc_type: String, // "regularity", "liaison" or "super_special"
+    pub start_location: Option<String>,
+    // Scheduled start as time of day in centiseconds
+    pub scheduled_start_centiseconds: Option<i64>,
+    // Time allowed for a liaison, in centiseconds
+    pub target_time_centiseconds: Option<i64>,
+    pub total_distance_meters: Option<i64>,
+    pub notes: Option<String>,
+    // Set while the PC is in the trash
+    #[serde(default)]
+    pub deleted_at: Option<String>,
     pub created_at: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatePCRequest {
+    pub id: i64,
+    pub name: Option<String>,
+    pub pc_type: String,
+    pub start_location: Option<String>,
+    pub scheduled_start_centiseconds: Option<i64>,
+    pub target_time_centiseconds: Option<i64>,
+    pub total_distance_meters: Option<i64>,
+    pub notes: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ReferenceEntry {
     pub id: i64,
@@ -24,14 +104,24 @@ pub struct ReferenceEntry {
     pub seconds: i32,
     pub centiseconds: i32,
     pub event_type: String,
-    pub speed: i32,
+    // Average speed in thousandths of km/h (47.5 km/h is stored as 47500)
+    pub speed: i64,
     pub extra_value: Option<f64>,
     pub is_control_zone: bool,
     pub order_index: i32,
+    // "time" when the passage time is given, "distance" when it is computed
+    // from `distance_meters` and the speeds before it
+    pub defined_by: String,
+    // Distance from the LAR, computed for references defined by time
+    pub distance_meters: Option<f64>,
+    pub note: Option<String>,
+    // Physical landmark at the reference ("cartel km 23", "puente", "T a la derecha")
+    pub landmark: Option<String>,
+    pub has_tulip: bool,
     pub created_at: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CreateReferenceRequest {
     pub pc_id: i64,
     pub hours: i32,
@@ -39,8 +129,12 @@ pub struct CreateReferenceRequest {
     pub seconds: i32,
     pub centiseconds: i32,
     pub event_type: String,
-    pub speed: i32,
+    pub speed: i64,
     pub extra_value: Option<f64>,
+    pub defined_by: Option<String>, // Defaults to "time"
+    pub distance_meters: Option<f64>,
+    pub note: Option<String>,
+    pub landmark: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -51,6 +145,527 @@ pub struct UpdateReferenceRequest {
     pub seconds: i32,
     pub centiseconds: i32,
     pub event_type: String,
-    pub speed: i32,
+    pub speed: i64,
     pub extra_value: Option<f64>,
+    pub defined_by: Option<String>, // Defaults to "time"
+    pub distance_meters: Option<f64>,
+    pub note: Option<String>,
+    pub landmark: Option<String>,
+}
+
+// Tulip diagram attached to a reference, the image is stored in the database
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReferenceTulip {
+    pub id: i64,
+    pub reference_id: i64,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    pub description: Option<String>,
+    pub created_at: String,
+}
+
+impl ReferenceEntry {
+    pub fn time_centiseconds(&self) -> i64 {
+        self.hours as i64 * 360000
+            + self.minutes as i64 * 6000
+            + self.seconds as i64 * 100
+            + self.centiseconds as i64
+    }
+
+    pub fn set_time_centiseconds(&mut self, total: i64) {
+        self.hours = (total / 360000) as i32;
+        self.minutes = (total % 360000 / 6000) as i32;
+        self.seconds = (total % 6000 / 100) as i32;
+        self.centiseconds = (total % 100) as i32;
+    }
+
+    // CVT, CVD and CVR start a new speed segment (LAR sets the initial one)
+    pub fn is_speed_change(&self) -> bool {
+        matches!(self.event_type.as_str(), "CVT" | "CVD" | "CVR")
+    }
+}
+
+// One row of the interval table of a PC, derived from its compiled route
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteInterval {
+    pub reference_id: i64,
+    pub event_type: String,
+    pub time_centiseconds: i64,
+    // Cumulative ideal distance from the LAR
+    pub distance_meters: f64,
+    // Since the previous reference, None for the LAR
+    pub delta_centiseconds: Option<i64>,
+    pub delta_meters: Option<f64>,
+    // Speed driven to reach this reference, thousandths of km/h
+    pub segment_speed: i64,
+    // Clock shift of an ADL (positive) or ATR (negative), in centiseconds
+    pub time_shift_centiseconds: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarchTableOptions {
+    pub step_by: String, // "distance" or "time"
+    // Meters when stepping by distance, seconds when stepping by time
+    pub step: f64,
+}
+
+// One line of a "tabla de marcha": either a step mark or a reference
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarchRow {
+    pub time_centiseconds: i64,
+    pub distance_meters: f64,
+    // Speed driven from this point on, thousandths of km/h
+    pub speed: i64,
+    pub reference_id: Option<i64>,
+    pub event_type: Option<String>,
+    pub landmark: Option<String>,
+    pub is_speed_change: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoadbookLineError {
+    pub line: usize, // 1-based, the header is line 1
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoadbookImportReport {
+    // False for a dry run, or when lines could not be read and nothing was written
+    pub applied: bool,
+    // Field name to the column header it was read from
+    pub mapping: HashMap<String, String>,
+    pub rows_read: usize,
+    pub references_created: usize,
+    pub pcs_created: Vec<i32>,
+    pub errors: Vec<RoadbookLineError>,
+    pub validation: Vec<ValidationReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct XlsxSheetPreview {
+    pub name: String,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct XlsxImportOptions {
+    pub sheet_name: Option<String>, // First sheet when missing
+    pub header_row: Option<usize>,  // 1-based sheet row, first used row when missing
+    pub mapping: Option<HashMap<String, String>>,
+    pub replace_existing: Option<bool>,
+}
+
+// Column mapping saved for an organiser whose spreadsheets share a layout
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportTemplate {
+    pub id: i64,
+    pub name: String,
+    pub organizer: Option<String>,
+    pub sheet_name: Option<String>,
+    pub header_row: Option<usize>,
+    pub mapping: HashMap<String, String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveImportTemplateRequest {
+    pub id: Option<i64>, // Updates the template when set
+    pub name: String,
+    pub organizer: Option<String>,
+    pub sheet_name: Option<String>,
+    pub header_row: Option<usize>,
+    pub mapping: HashMap<String, String>,
+}
+
+// A line of a PDF roadbook read as a reference
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfCandidate {
+    pub line: usize, // Line of the extracted text, counted across pages
+    pub page: usize,
+    pub text: String,
+    pub pc_number: i32,
+    // pc_id is the existing PC with that number, 0 when it would be created
+    pub reference: CreateReferenceRequest,
+    pub confidence: f64, // 0 to 1
+    pub issues: Vec<String>,
+}
+
+// How a proposed reference compares with what the PC already has.
+// status: "added", "changed", "unchanged", or for references missing from
+// the file "removed" (replacing) or "kept" (appending)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReferenceDiff {
+    pub pc_number: i32,
+    pub status: String,
+    pub existing: Option<ReferenceEntry>,
+    pub proposed: Option<CreateReferenceRequest>,
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PdfImportOptions {
+    pub min_confidence: Option<f64>, // Defaults to 0.6
+    pub lines: Option<Vec<usize>>,   // Picked by hand, overrides the confidence
+    pub replace_existing: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfImportPreview {
+    pub pages: usize,
+    pub lines_read: usize,
+    // Most confident first
+    pub candidates: Vec<PdfCandidate>,
+    pub accepted_lines: Vec<usize>,
+    pub diff: Vec<ReferenceDiff>,
+    // Dry run of importing the accepted candidates
+    pub report: RoadbookImportReport,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaceBundleImportReport {
+    pub applied: bool,
+    pub race: Option<Race>,
+    // Race already holding the bundle's identity, set when no mode resolved it
+    pub conflict: Option<Race>,
+    // With a conflict, how each PC of either side compares, by PC number
+    #[serde(default)]
+    pub pc_conflicts: Vec<BundlePCConflict>,
+    pub pcs_created: Vec<i32>,
+    pub pcs_replaced: Vec<i32>,
+    pub references_created: usize,
+    pub settings_imported: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundlePCConflict {
+    pub pc_number: i32,
+    // "new" (only in the bundle), "changed", "same" or "local" (only in the
+    // race here, kept by "merge" and trashed by "replace")
+    pub status: String,
+    pub local_references: usize,
+    pub bundle_references: usize,
+}
+
+// One code of a race sent as a series of QR codes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QrChunk {
+    pub index: usize, // 1-based
+    pub total: usize,
+    pub payload: String, // Text held by the code
+    pub svg: String,
+}
+
+// How far the scanning of a QR series got
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QrTransferStatus {
+    pub series_id: String,
+    pub total: usize,
+    pub received: Vec<usize>,
+    pub missing: Vec<usize>,
+    // Every code is there and the bundle checked out
+    pub complete: bool,
+    pub race_name: Option<String>,
+}
+
+// A copy of the database in the backups folder
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub reason: String, // "startup", "before_import", "race_locked"...
+    pub created_at: String,
+    pub size_bytes: u64,
+}
+
+// Something wrong found by the database check
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IntegrityIssue {
+    // "integrity", "foreign_key", "orphan_reference", "duplicate_order" or "order_gap"
+    pub kind: String,
+    pub message: String,
+    pub race_id: Option<i64>,
+    pub pc_id: Option<i64>,
+    pub rows: usize,
+    // Whether repair_database can fix it without losing anything still in use
+    pub repairable: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    // What is still wrong
+    pub issues: Vec<IntegrityIssue>,
+    // What a repair fixed, empty for a check
+    pub repaired: Vec<IntegrityIssue>,
+    // Taken before repairing
+    pub backup: Option<BackupInfo>,
+}
+
+// A race as it is in a backup, trashed ones included
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupRace {
+    pub id: i64,
+    pub uid: String,
+    pub name: String,
+    pub event_date: Option<String>,
+    pub pc_count: usize,
+    pub reference_count: usize,
+    pub locked_at: Option<String>,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValidationIssue {
+    pub pc_id: i64,
+    pub reference_id: Option<i64>,
+    pub severity: String, // "error" or "warning"
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValidationReport {
+    pub pc_id: i64,
+    pub pc_number: i32,
+    pub error_count: usize,
+    pub warning_count: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+// Roadbook checksum of a PC. `fingerprint` is the short form read aloud.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PCChecksum {
+    pub pc_id: i64,
+    pub pc_number: i32,
+    pub reference_count: usize,
+    pub hash: String, // SHA-256, hex
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaceChecksum {
+    pub race_id: i64,
+    pub hash: String,
+    pub fingerprint: String,
+    pub pcs: Vec<PCChecksum>,
+}
+
+// One PC compared against the fingerprint read from another machine
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PCChecksumComparison {
+    pub pc_number: i32,
+    pub local: Option<String>,
+    pub other: Option<String>,
+    pub matches: bool,
+}
+
+// A recorded change: the race (pc_id empty) or one PC as it was afterwards
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Revision {
+    pub id: i64,
+    pub race_id: i64,
+    pub pc_id: Option<i64>,
+    pub action: String, // Command that made the change
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PCSnapshot {
+    pub pc: PC,
+    pub references: Vec<ReferenceEntry>,
+    // None in revisions recorded before tulips were kept
+    #[serde(default)]
+    pub tulips: Option<Vec<ReferenceTulip>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReferenceChange {
+    pub reference_id: i64,
+    pub status: String, // "added", "removed" or "changed"
+    pub before: Option<ReferenceEntry>,
+    pub after: Option<ReferenceEntry>,
+    pub fields: Vec<FieldChange>, // Only for "changed"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PCRevisionDiff {
+    pub from: Revision,
+    pub to: Option<Revision>, // None when compared with the current state
+    pub pc_fields: Vec<FieldChange>,
+    pub references: Vec<ReferenceChange>,
+}
+
+// One change of an organiser bulletin. PCs are given by number and references
+// by their 1-based position in the PC, as printed in the roadbook. Operations
+// run in order, so positions refer to the PC as left by the previous ones.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BulletinOperation {
+    // Moves the time of references `from`..=`to` (may be negative). Distance-defined
+    // references in the range become defined by their new time
+    ShiftTimes {
+        pc_number: i32,
+        from: usize,
+        to: usize,
+        offset_centiseconds: i64,
+    },
+    // New speed from the LAR/CVT/CVD/CVR at `position` up to the next speed change
+    SetSpeed {
+        pc_number: i32,
+        position: usize,
+        speed: i64,
+    },
+    // Inserts before `position`, one past the last reference appends. pc_id is ignored
+    InsertReference {
+        pc_number: i32,
+        position: usize,
+        reference: CreateReferenceRequest,
+    },
+    RemoveReference {
+        pc_number: i32,
+        position: usize,
+    },
+    SetControlZone {
+        pc_number: i32,
+        from: usize,
+        to: usize,
+        is_control_zone: bool,
+    },
+}
+
+impl BulletinOperation {
+    pub fn pc_number(&self) -> i32 {
+        match self {
+            BulletinOperation::ShiftTimes { pc_number, .. }
+            | BulletinOperation::SetSpeed { pc_number, .. }
+            | BulletinOperation::InsertReference { pc_number, .. }
+            | BulletinOperation::RemoveReference { pc_number, .. }
+            | BulletinOperation::SetControlZone { pc_number, .. } => *pc_number,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bulletin {
+    pub id: i64,
+    pub race_id: i64,
+    pub name: String,
+    pub operations: Vec<BulletinOperation>,
+    pub status: String, // "draft", "applied" or "reverted"
+    pub applied_at: Option<String>,
+    pub reverted_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveBulletinRequest {
+    pub id: Option<i64>, // Updates the draft when set
+    pub race_id: i64,
+    pub name: String,
+    pub operations: Vec<BulletinOperation>,
+}
+
+// The affected PCs before and after applying a bulletin, with the tulips of
+// their references so removed ones come back with theirs
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulletinUndo {
+    pub before: Vec<PCSnapshot>,
+    pub after: Vec<PCSnapshot>,
+    pub tulips: Vec<ReferenceTulip>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulletinPCChange {
+    pub pc_id: i64,
+    pub pc_number: i32,
+    pub references: Vec<ReferenceChange>,
+    pub validation: ValidationReport,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BulletinPreview {
+    pub bulletin: Bulletin,
+    pub applied: bool,
+    pub pcs: Vec<BulletinPCChange>,
+}
+
+// A race or PC in the trash. PCs of a race in the trash are not listed on
+// their own, they come back with the race.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashItem {
+    pub kind: String, // "race" or "pc"
+    pub id: i64,
+    pub race_id: i64,
+    pub race_name: String,
+    pub pc_number: Option<i32>,
+    pub pc_name: Option<String>,
+    pub pc_count: usize,
+    pub reference_count: usize,
+    pub deleted_at: String,
+    // When it will be purged, empty if the trash is never emptied automatically
+    pub purge_at: Option<String>,
+}
+
+// An edit that can be undone, or redone once undone
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EditHistoryEntry {
+    pub id: i64,
+    pub action: String, // Command that made the edit, "delete_reference"
+    pub race_id: Option<i64>,
+    pub undone: bool,
+    pub created_at: String,
+}
+
+// A race touched by an edit, `race` empty when it did not exist. `whole` races
+// bring all their PCs along, and their settings so a deletion can be undone.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaceEditState {
+    pub id: i64,
+    pub race: Option<Race>,
+    pub whole: bool,
+    pub settings: Option<HashMap<String, String>>,
+}
+
+// A PC touched by an edit. `references` is empty when only the PC row
+// changed (renumbering); tulips are only kept when they changed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PCEditState {
+    pub id: i64,
+    pub pc: Option<PC>,
+    pub references: Option<Vec<ReferenceEntry>>,
+    pub tulips: Vec<ReferenceTulip>,
+}
+
+// The rows an edit touched, as they were before or after it
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EditState {
+    pub races: Vec<RaceEditState>,
+    pub pcs: Vec<PCEditState>,
+}
+
+impl EditState {
+    // Marks a race created by the edit, captured afterwards
+    pub fn created_race(&mut self, id: i64, whole: bool) {
+        self.races.push(RaceEditState { id, race: None, whole, settings: None });
+    }
+
+    // Marks a PC created by the edit, captured afterwards
+    pub fn created_pc(&mut self, id: i64) {
+        self.pcs.push(PCEditState { id, pc: None, references: Some(Vec::new()), tulips: Vec::new() });
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DuplicateOptions {
+    // Shift applied to every reference time (may be negative), wraps around midnight
+    pub time_offset_centiseconds: Option<i64>,
+    // Factor applied to every speed (> 0), references keep their distances and
+    // the passage times are worked out again
+    pub speed_scale: Option<f64>,
 }
@@ -0,0 +1,395 @@
+use crate::march_table::{format_speed, format_time};
+use crate::models::{CreateReferenceRequest, PdfCandidate, ReferenceDiff, ReferenceEntry};
+use crate::roadbook::{self, Records};
+
+// Roadbooks published as PDF: the text layer is read line by line and every
+// line carrying a time is proposed as a reference. Nothing is trusted blindly,
+// each proposal gets a confidence from how much of it was actually found on
+// the line, and the accepted ones go through the regular roadbook import.
+
+// Proposals below this confidence are left out unless picked by hand
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.6;
+
+pub struct TextLine {
+    pub number: usize, // Counted across pages, blank lines included
+    pub page: usize,
+    pub text: String,
+}
+
+// Page count and the non-blank lines of the text layer
+pub fn extract_lines(path: &str) -> Result<(usize, Vec<TextLine>), String> {
+    // The extractor panics on some malformed files instead of failing
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_by_pages(path))
+        .map_err(|_| "The PDF could not be read".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let lines = pages
+        .iter()
+        .enumerate()
+        .flat_map(|(page, text)| text.lines().map(move |line| (page + 1, line.trim().to_string())))
+        .enumerate()
+        .filter(|(_, (_, text))| !text.is_empty())
+        .map(|(index, (page, text))| TextLine { number: index + 1, page, text })
+        .collect();
+
+    Ok((pages.len(), lines))
+}
+
+fn event_code(token: &str) -> Option<&'static str> {
+    let word = token.trim_matches(|c: char| !c.is_alphanumeric()).to_uppercase();
+    match word.as_str() {
+        "LAR" | "LARGADA" => Some("LAR"),
+        "REF" | "REFERENCIA" => Some("REF"),
+        "ADL" | "ADELANTO" => Some("ADL"),
+        "ATR" | "ATRASO" => Some("ATR"),
+        "CVT" => Some("CVT"),
+        "CVD" => Some("CVD"),
+        "CVR" => Some("CVR"),
+        _ => None,
+    }
+}
+
+// "PC 3", "P.C. 3", "PC Nº 3", "PC-3" and "PC3" at the start of a line.
+// Returns the number and how many tokens it took.
+fn pc_heading(tokens: &[&str]) -> Option<(i32, usize)> {
+    let first = tokens.first()?.to_uppercase().replace('.', "");
+    let number = |value: &str| value.trim_start_matches(['-', '#']).parse::<i32>().ok().filter(|n| *n > 0);
+
+    if let Some(rest) = first.strip_prefix("PC").filter(|rest| !rest.is_empty()) {
+        return number(rest).map(|n| (n, 1));
+    }
+    if first != "PC" {
+        return None;
+    }
+    match tokens.get(1).map(|t| t.to_uppercase()) {
+        Some(t) if matches!(t.as_str(), "N°" | "Nº" | "NRO" | "NRO." | "NO" | "NO.") => {
+            tokens.get(2).and_then(|t| number(t)).map(|n| (n, 3))
+        }
+        Some(t) => number(&t).map(|n| (n, 2)),
+        None => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Unit {
+    None,
+    Speed,
+    Seconds,
+    // Kilometre marks, never taken as a speed or as seconds
+    Distance,
+}
+
+// A number with the unit written next to it, if any ("45,5 km/h", "30s")
+fn number_token(tokens: &[&str], index: usize) -> Option<(f64, Unit)> {
+    let token = tokens[index].to_lowercase();
+    let next = tokens.get(index + 1).map(|t| t.to_lowercase()).unwrap_or_default();
+
+    let (value, suffix) = match token.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',')) {
+        Some(0) => return None,
+        Some(split) if token[split..].contains(':') => return None,
+        Some(split) => token.split_at(split),
+        None => (token.as_str(), next.as_str()),
+    };
+    let unit = match suffix {
+        "km/h" | "kmh" => Unit::Speed,
+        "km" => Unit::Distance,
+        "s" | "seg" | "seg." | "segundos" | "\"" => Unit::Seconds,
+        _ => Unit::None,
+    };
+    roadbook::parse_decimal(value).map(|value| (value, unit))
+}
+
+struct LastRow {
+    time_centiseconds: i64,
+    speed: i64,
+}
+
+// Every line with a time, in document order
+pub fn detect(lines: &[TextLine]) -> Vec<PdfCandidate> {
+    let mut candidates = Vec::new();
+    let mut current_pc: Option<i32> = None;
+    let mut last_rows: Vec<(i32, LastRow)> = Vec::new();
+
+    for TextLine { number, page, text } in lines {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let mut start = 0;
+        if let Some((pc_number, taken)) = pc_heading(&tokens) {
+            current_pc = Some(pc_number);
+            start = taken;
+        }
+
+        let Some((time_index, time_centiseconds)) = tokens.iter().enumerate().skip(start).find_map(|(i, token)| {
+            let token = token.trim_end_matches(|c: char| !c.is_ascii_digit());
+            token.contains(':').then(|| roadbook::parse_time(token).ok()).flatten().map(|time| (i, time))
+        }) else {
+            continue;
+        };
+
+        let mut issues = Vec::new();
+        let mut confidence: f64 = 0.4;
+
+        let pc_number = current_pc.unwrap_or_else(|| {
+            issues.push("No PC heading before this line, assumed PC 1".to_string());
+            confidence -= 0.2;
+            1
+        });
+        let last = last_rows.iter().find(|(number, _)| *number == pc_number).map(|(_, row)| row);
+
+        let event_type = match tokens.iter().skip(start).find_map(|token| event_code(token)) {
+            Some(code) => {
+                confidence += 0.25;
+                code
+            }
+            None => {
+                let assumed = if last.is_some() { "REF" } else { "LAR" };
+                issues.push(format!("No event type, assumed {}", assumed));
+                assumed
+            }
+        };
+
+        // Numbers after the time: the speed and, for ADL/ATR the seconds, for
+        // CVD the km mark
+        let numbers: Vec<(f64, Unit)> = (time_index + 1..tokens.len()).filter_map(|i| number_token(&tokens, i)).collect();
+        let speed_number = numbers
+            .iter()
+            .position(|(_, unit)| *unit == Unit::Speed)
+            .or_else(|| numbers.iter().position(|(value, unit)| *unit == Unit::None && (5.0..=200.0).contains(value)));
+        let speed = match speed_number {
+            Some(index) => {
+                confidence += 0.2;
+                (numbers[index].0 * 1000.0).round() as i64
+            }
+            None => match last {
+                // ADL and ATR lines rarely repeat the speed they keep
+                Some(row) if matches!(event_type, "ADL" | "ATR") => {
+                    confidence += 0.2;
+                    row.speed
+                }
+                Some(row) => {
+                    issues.push("No speed, kept the one of the previous line".to_string());
+                    row.speed
+                }
+                None => {
+                    issues.push("No speed found".to_string());
+                    0
+                }
+            },
+        };
+
+        let extra_value = if matches!(event_type, "ADL" | "ATR") {
+            let seconds = numbers
+                .iter()
+                .position(|(_, unit)| *unit == Unit::Seconds)
+                .or_else(|| (0..numbers.len()).find(|i| Some(*i) != speed_number && numbers[*i].1 == Unit::None));
+            if seconds.is_none() {
+                issues.push(format!("No seconds found for the {}", event_type));
+                confidence -= 0.1;
+            }
+            seconds.map(|i| numbers[i].0)
+        } else if event_type == "CVD" {
+            let distance = numbers.iter().find(|(_, unit)| *unit == Unit::Distance).map(|(value, _)| *value);
+            if distance.is_none() {
+                issues.push("No km found for the CVD".to_string());
+                confidence -= 0.1;
+            }
+            distance
+        } else {
+            None
+        };
+
+        match last {
+            Some(row) if time_centiseconds <= row.time_centiseconds => {
+                issues.push("Time is not after the previous line of the PC".to_string());
+            }
+            _ => confidence += 0.15,
+        }
+
+        let row = LastRow { time_centiseconds, speed };
+        match last_rows.iter_mut().find(|(number, _)| *number == pc_number) {
+            Some((_, last)) => *last = row,
+            None => last_rows.push((pc_number, row)),
+        }
+
+        candidates.push(PdfCandidate {
+            line: *number,
+            page: *page,
+            text: text.clone(),
+            pc_number,
+            reference: CreateReferenceRequest {
+                pc_id: 0,
+                hours: (time_centiseconds / 360000) as i32,
+                minutes: (time_centiseconds % 360000 / 6000) as i32,
+                seconds: (time_centiseconds % 6000 / 100) as i32,
+                centiseconds: (time_centiseconds % 100) as i32,
+                event_type: event_type.to_string(),
+                speed,
+                extra_value,
+                defined_by: None,
+                distance_meters: None,
+                note: None,
+                landmark: None,
+            },
+            confidence: (confidence.max(0.0) * 100.0).round() / 100.0,
+            issues,
+        });
+    }
+
+    candidates
+}
+
+fn request_time(request: &CreateReferenceRequest) -> i64 {
+    request.hours as i64 * 360000 + request.minutes as i64 * 6000 + request.seconds as i64 * 100 + request.centiseconds as i64
+}
+
+// Compares the proposals of one PC with its references, matching them by time
+pub fn diff(pc_number: i32, existing: &[ReferenceEntry], proposed: &[&PdfCandidate], replace_existing: bool) -> Vec<ReferenceDiff> {
+    let mut matched = vec![false; existing.len()];
+    let mut entries = Vec::new();
+
+    for candidate in proposed {
+        let request = &candidate.reference;
+        let time = request_time(request);
+        let found = existing
+            .iter()
+            .enumerate()
+            .position(|(i, reference)| !matched[i] && reference.time_centiseconds() == time);
+
+        let status = match found {
+            Some(i) => {
+                matched[i] = true;
+                let reference = &existing[i];
+                let same = reference.event_type == request.event_type
+                    && reference.speed == request.speed
+                    && reference.extra_value == request.extra_value;
+                if same { "unchanged" } else { "changed" }
+            }
+            None => "added",
+        };
+        entries.push(ReferenceDiff {
+            pc_number,
+            status: status.to_string(),
+            existing: found.map(|i| existing[i].clone()),
+            proposed: Some(request.clone()),
+            line: Some(candidate.line),
+        });
+    }
+
+    for (reference, _) in existing.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+        entries.push(ReferenceDiff {
+            pc_number,
+            status: if replace_existing { "removed" } else { "kept" }.to_string(),
+            existing: Some(reference.clone()),
+            proposed: None,
+            line: None,
+        });
+    }
+
+    let time = |entry: &ReferenceDiff| match (&entry.proposed, &entry.existing) {
+        (Some(request), _) => request_time(request),
+        (None, Some(reference)) => reference.time_centiseconds(),
+        (None, None) => 0,
+    };
+    entries.sort_by_key(time);
+    entries
+}
+
+// Accepted proposals as a roadbook table, so they are imported like a CSV
+pub fn to_table(candidates: &[&PdfCandidate]) -> (Vec<String>, Records) {
+    let headers = ["pc", "time", "event_type", "speed", "extra_value"].iter().map(|h| h.to_string()).collect();
+    let records = candidates
+        .iter()
+        .map(|candidate| {
+            let request = &candidate.reference;
+            let record = vec![
+                candidate.pc_number.to_string(),
+                format_time(request_time(request)),
+                request.event_type.clone(),
+                format_speed(request.speed),
+                request.extra_value.map(|v| v.to_string()).unwrap_or_default(),
+            ];
+            (candidate.line, Ok(record))
+        })
+        .collect();
+
+    (headers, records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{reference, TEN};
+
+    fn lines(texts: &[&str]) -> Vec<TextLine> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| TextLine { number: i + 1, page: 1, text: text.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn cvd_takes_the_km_mark_and_adl_atr_the_seconds() {
+        let candidates = detect(&lines(&[
+            "PC 2",
+            "10:00:00 LAR 36 km/h",
+            "10:05:00 CVD 3,5 km 72 km/h",
+            "10:06:00 ADL 30 s",
+            "10:08:00 ATR 4 km 72 km/h 20",
+            "10:09:00 CVD 60 km/h",
+        ]));
+        assert_eq!(candidates.len(), 5);
+        assert!(candidates.iter().all(|c| c.pc_number == 2));
+
+        let cvd = &candidates[1];
+        assert_eq!(cvd.reference.event_type, "CVD");
+        assert_eq!(cvd.reference.speed, 72000);
+        assert_eq!(cvd.reference.extra_value, Some(3.5));
+        assert!(cvd.issues.is_empty());
+
+        // The ADL keeps the speed of the CVD
+        let adl = &candidates[2];
+        assert_eq!(adl.reference.speed, 72000);
+        assert_eq!(adl.reference.extra_value, Some(30.0));
+
+        // A km mark is never taken as the seconds
+        assert_eq!(candidates[3].reference.extra_value, Some(20.0));
+
+        let no_km = &candidates[4];
+        assert_eq!(no_km.reference.extra_value, None);
+        assert_eq!(no_km.issues, vec!["No km found for the CVD".to_string()]);
+        assert!(no_km.confidence < cvd.confidence);
+    }
+
+    #[test]
+    fn a_bare_km_mark_is_not_a_speed() {
+        let candidates = detect(&lines(&["PC 1", "10:00:00 LAR 45 km/h", "10:02:00 REF 12 km"]));
+        let reference = &candidates[1].reference;
+        assert_eq!(reference.speed, 45000);
+        assert_eq!(reference.extra_value, None);
+        assert_eq!(candidates[1].issues, vec!["No speed, kept the one of the previous line".to_string()]);
+    }
+
+    #[test]
+    fn diff_matches_the_proposals_by_time() {
+        let mut cvd = reference(2, "CVD", TEN + 30000, 72000);
+        cvd.extra_value = Some(3.0);
+        let existing = vec![reference(1, "LAR", TEN, 36000), cvd, reference(3, "REF", TEN + 60000, 72000)];
+        let candidates = detect(&lines(&[
+            "PC 1",
+            "10:00:00 LAR 36 km/h",
+            "10:05:00 CVD 3,5 km 72 km/h",
+            "10:07:00 REF 72 km/h",
+        ]));
+        let proposed: Vec<&PdfCandidate> = candidates.iter().collect();
+
+        let statuses = |replace_existing| {
+            diff(1, &existing, &proposed, replace_existing)
+                .into_iter()
+                .map(|entry| entry.status)
+                .collect::<Vec<_>>()
+        };
+        // The CVD moved from km 3 to km 3.5, the REF at 10:10 is not in the PDF
+        assert_eq!(statuses(false), vec!["unchanged", "changed", "added", "kept"]);
+        assert_eq!(statuses(true), vec!["unchanged", "changed", "added", "removed"]);
+    }
+}
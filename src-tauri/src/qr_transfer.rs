@@ -0,0 +1,205 @@
+use crate::models::{QrChunk, QrTransferStatus};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use qrcode::{render::svg, EcLevel, QrCode};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+// Race bundles passed through a series of QR codes, for when there is no
+// network or USB stick at hand. The bundle JSON is deflated, base64 encoded
+// and cut in chunks; every chunk carries the id of its series and its place
+// in it, so the codes can be scanned in any order and more than once:
+//
+//     KRQ1|<series id>|<index>/<total>|<data>
+//
+// The series id is the start of the SHA-256 of the compressed bundle and is
+// checked again once all chunks are put back together.
+
+const PREFIX: &str = "KRQ1";
+
+// Characters of data per code, small enough for a phone camera to read
+// from a laptop screen
+pub const DEFAULT_CHUNK_SIZE: usize = 800;
+const MIN_CHUNK_SIZE: usize = 100;
+const MAX_CHUNK_SIZE: usize = 2000;
+
+struct Chunk {
+    series_id: String,
+    index: usize,
+    total: usize,
+    data: String,
+}
+
+fn series_id(compressed: &[u8]) -> String {
+    Sha256::digest(compressed)[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_chunk(payload: &str) -> Result<Chunk, String> {
+    let invalid = || "Not a Kiroshi race QR code".to_string();
+    let mut parts = payload.trim().splitn(4, '|');
+    if parts.next() != Some(PREFIX) {
+        return Err(invalid());
+    }
+    let series_id = parts.next().ok_or_else(invalid)?.to_string();
+    let (index, total) = parts.next().and_then(|position| position.split_once('/')).ok_or_else(invalid)?;
+    let index = index.parse::<usize>().map_err(|_| invalid())?;
+    let total = total.parse::<usize>().map_err(|_| invalid())?;
+    let data = parts.next().ok_or_else(invalid)?.to_string();
+    if index == 0 || index > total {
+        return Err(invalid());
+    }
+    Ok(Chunk { series_id, index, total, data })
+}
+
+// Cuts the bundle JSON in QR codes, rendered as SVG
+pub fn encode(json: &str, chunk_size: Option<usize>) -> Result<Vec<QrChunk>, String> {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+
+    let id = series_id(&compressed);
+    let data = STANDARD.encode(&compressed);
+    // Base64 is plain ASCII, so slicing by bytes is safe
+    let pieces: Vec<&str> = data.as_bytes().chunks(chunk_size).map(|piece| std::str::from_utf8(piece).unwrap_or_default()).collect();
+    let total = pieces.len();
+
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let payload = format!("{}|{}|{}/{}|{}", PREFIX, id, i + 1, total, piece);
+            let code = QrCode::with_error_correction_level(payload.as_bytes(), EcLevel::M).map_err(|e| e.to_string())?;
+            let svg = code.render::<svg::Color>().min_dimensions(320, 320).build();
+            Ok(QrChunk { index: i + 1, total, payload, svg })
+        })
+        .collect()
+}
+
+// Puts scanned payloads back together. Returns how far the transfer got and,
+// once every chunk is there and the series id checks out, the bundle JSON.
+pub fn decode(payloads: &[String]) -> Result<(QrTransferStatus, Option<String>), String> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    for payload in payloads.iter().filter(|payload| !payload.trim().is_empty()) {
+        let chunk = parse_chunk(payload)?;
+        if let Some(first) = chunks.first() {
+            if chunk.series_id != first.series_id || chunk.total != first.total {
+                return Err("The codes belong to different transfers, scan only the codes of one race".to_string());
+            }
+        }
+        match chunks.iter().find(|c| c.index == chunk.index) {
+            Some(seen) if seen.data != chunk.data => {
+                return Err(format!("Code {} was read twice with different contents", chunk.index));
+            }
+            Some(_) => {}
+            None => chunks.push(chunk),
+        }
+    }
+
+    let Some(first) = chunks.first() else {
+        return Err("No QR codes were read".to_string());
+    };
+    let (id, total) = (first.series_id.clone(), first.total);
+    chunks.sort_by_key(|chunk| chunk.index);
+
+    let received: Vec<usize> = chunks.iter().map(|chunk| chunk.index).collect();
+    let missing: Vec<usize> = (1..=total).filter(|index| !received.contains(index)).collect();
+    let mut status = QrTransferStatus {
+        series_id: id.clone(),
+        total,
+        received,
+        missing,
+        complete: false,
+        race_name: None,
+    };
+    if !status.missing.is_empty() {
+        return Ok((status, None));
+    }
+
+    let data: String = chunks.iter().map(|chunk| chunk.data.as_str()).collect();
+    let compressed = STANDARD.decode(data).map_err(|_| "The codes are damaged, scan them again".to_string())?;
+    if series_id(&compressed) != id {
+        return Err("The codes are damaged, scan them again".to_string());
+    }
+    let mut json = String::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .read_to_string(&mut json)
+        .map_err(|_| "The codes are damaged, scan them again".to_string())?;
+
+    status.complete = true;
+    Ok((status, Some(json)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pseudo-random digits, so the bundle does not deflate to a single code
+    fn bundle_json(seed: u64) -> String {
+        let mut state = seed;
+        let digits: String = (0..3000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                char::from(b'0' + (state >> 59) as u8 % 10)
+            })
+            .collect();
+        format!("{{\"race\":{{\"name\":\"Prueba\"}},\"data\":\"{}\"}}", digits)
+    }
+
+    fn payloads(json: &str) -> Vec<String> {
+        encode(json, Some(MIN_CHUNK_SIZE)).unwrap().into_iter().map(|chunk| chunk.payload).collect()
+    }
+
+    #[test]
+    fn round_trip_in_any_order() {
+        let json = bundle_json(1);
+        let mut scanned = payloads(&json);
+        assert!(scanned.len() > 3);
+        // Scanned backwards, one code twice and an empty read in between
+        scanned.reverse();
+        scanned.push(scanned[1].clone());
+        scanned.insert(2, String::new());
+
+        let (status, decoded) = decode(&scanned).unwrap();
+        assert!(status.complete);
+        assert!(status.missing.is_empty());
+        assert_eq!(status.received, (1..=status.total).collect::<Vec<_>>());
+        assert_eq!(decoded.as_deref(), Some(json.as_str()));
+    }
+
+    #[test]
+    fn missing_chunks_are_reported() {
+        let mut scanned = payloads(&bundle_json(2));
+        let total = scanned.len();
+        scanned.remove(3);
+        scanned.remove(1);
+
+        let (status, decoded) = decode(&scanned).unwrap();
+        assert!(!status.complete);
+        assert!(decoded.is_none());
+        assert_eq!(status.total, total);
+        assert_eq!(status.missing, vec![2, 4]);
+    }
+
+    #[test]
+    fn foreign_and_damaged_codes_are_rejected() {
+        let first = payloads(&bundle_json(3));
+        let second = payloads(&bundle_json(4));
+
+        assert!(decode(&[]).is_err());
+        assert!(decode(&["https://example.com".to_string()]).is_err());
+        assert!(decode(&[first[0].clone(), second[1].clone()]).is_err());
+
+        // Same code read twice with different contents
+        let mut changed = first[0].clone();
+        let last = changed.pop().unwrap();
+        changed.push(if last == 'A' { 'B' } else { 'A' });
+        assert!(decode(&[first[0].clone(), changed.clone()]).is_err());
+
+        // A changed character only shows once the series is complete
+        let mut damaged = first.clone();
+        damaged[0] = changed;
+        assert!(decode(&damaged).is_err());
+    }
+}
@@ -11,12 +11,17 @@ pub struct RaceTimerState {
     pub raw_meters: f64,
     pub corrected_meters: f64,
     pub correction_factor: f64,
-    pub current_speed: f64,
+    // Current average speed in thousandths of km/h
+    pub current_speed: i64,
     pub is_running: bool,
     pub diff_snapshot: f64,
     pub odometer_meters: f64,
     // Race clock in centiseconds (hora de carrera)
     pub race_clock_centiseconds: i64,
+    // PC type being driven: "regularity", "liaison" or "super_special"
+    pub stage_type: String,
+    // Centiseconds left until the stage target time (liaisons only)
+    pub target_remaining_centiseconds: Option<i64>,
 }
 
 impl Default for RaceTimerState {
@@ -25,11 +30,13 @@ impl Default for RaceTimerState {
             raw_meters: 0.0,
             corrected_meters: 0.0,
             correction_factor: 1042.0,
-            current_speed: 0.0,
+            current_speed: 0,
             is_running: false,
             diff_snapshot: 0.0,
             odometer_meters: 0.0,
             race_clock_centiseconds: 0,
+            stage_type: "regularity".to_string(),
+            target_remaining_centiseconds: None,
         }
     }
 }
@@ -37,7 +44,7 @@ impl Default for RaceTimerState {
 struct TimerInternal {
     accumulated_meters: f64,
     correction_factor: f64,
-    current_speed: f64,
+    current_speed: i64, // Thousandths of km/h
     is_running: bool,
     diff_snapshot: f64,
     odometer_meters: f64,
@@ -45,6 +52,9 @@ struct TimerInternal {
     // Race clock tracking
     race_clock_start_centiseconds: i64, // LAR reference time in centiseconds
     race_clock_accumulated_centiseconds: f64, // Accumulated time since race start
+    // Stage tracking
+    stage_type: String,
+    target_clock_centiseconds: Option<i64>, // Race clock time the stage must end at
 }
 
 impl Default for TimerInternal {
@@ -52,13 +62,15 @@ impl Default for TimerInternal {
         Self {
             accumulated_meters: 0.0,
             correction_factor: 1042.0,
-            current_speed: 0.0,
+            current_speed: 0,
             is_running: false,
             diff_snapshot: 0.0,
             odometer_meters: 0.0,
             last_update: None,
             race_clock_start_centiseconds: 0,
             race_clock_accumulated_centiseconds: 0.0,
+            stage_type: "regularity".to_string(),
+            target_clock_centiseconds: None,
         }
     }
 }
@@ -77,6 +89,8 @@ impl TimerInternal {
             diff_snapshot: self.diff_snapshot,
             odometer_meters: self.odometer_meters,
             race_clock_centiseconds: race_clock,
+            stage_type: self.stage_type.clone(),
+            target_remaining_centiseconds: self.target_clock_centiseconds.map(|target| target - race_clock),
         }
     }
 
@@ -91,10 +105,11 @@ impl TimerInternal {
                 // Convert seconds to centiseconds (1 sec = 100 centiseconds)
                 self.race_clock_accumulated_centiseconds += elapsed_secs * 100.0;
 
-                // Update odometer only if speed > 0
-                if self.current_speed > 0.0 {
-                    // Speed is km/h, convert to m/s: speed / 3.6
-                    let meters_per_second = self.current_speed / 3.6;
+                // Update odometer only on regularity stages and if speed > 0.
+                // Liaisons and super specials have no ideal average to follow.
+                if self.stage_type == "regularity" && self.current_speed > 0 {
+                    // Speed is thousandths of km/h, i.e. metres per hour: m/s = speed / 3600
+                    let meters_per_second = self.current_speed as f64 / 3600.0;
                     self.accumulated_meters += meters_per_second * elapsed_secs;
                 }
             }
@@ -192,7 +207,7 @@ impl RaceTimer {
         timer.is_running
     }
 
-    pub fn set_speed(&self, speed: f64) {
+    pub fn set_speed(&self, speed: i64) {
         let mut timer = self.internal.lock().unwrap();
         // Update with old speed first
         timer.update();
@@ -259,6 +274,13 @@ impl RaceTimer {
         timer.race_clock_start_centiseconds = centiseconds;
         timer.race_clock_accumulated_centiseconds = 0.0;
     }
+
+    pub fn set_stage(&self, stage_type: String, target_clock_centiseconds: Option<i64>) {
+        let mut timer = self.internal.lock().unwrap();
+        timer.update();
+        timer.stage_type = stage_type;
+        timer.target_clock_centiseconds = target_clock_centiseconds;
+    }
 }
 
 // Tauri commands
@@ -283,7 +305,7 @@ pub fn toggle_race_timer(timer: State<RaceTimer>) -> RaceTimerState {
 }
 
 #[tauri::command]
-pub fn set_race_speed(timer: State<RaceTimer>, speed: f64) -> RaceTimerState {
+pub fn set_race_speed(timer: State<RaceTimer>, speed: i64) -> RaceTimerState {
     timer.set_speed(speed);
     timer.get_state()
 }
@@ -340,3 +362,13 @@ pub fn set_race_clock_start(timer: State<RaceTimer>, centiseconds: i64) -> RaceT
     timer.set_race_clock_start(centiseconds);
     timer.get_state()
 }
+
+#[tauri::command]
+pub fn set_race_stage(
+    timer: State<RaceTimer>,
+    stage_type: String,
+    target_clock_centiseconds: Option<i64>,
+) -> RaceTimerState {
+    timer.set_stage(stage_type, target_clock_centiseconds);
+    timer.get_state()
+}
@@ -0,0 +1,73 @@
+use crate::models::{FieldChange, PCSnapshot, ReferenceChange, ReferenceEntry};
+use serde::Serialize;
+use serde_json::Value;
+
+// Revisions keep the whole race row, or a whole PC with its references, as
+// JSON after every change. Diffs are computed from two snapshots field by
+// field, references are matched by id.
+
+// Bookkeeping fields that change without the roadbook changing
+const IGNORED_FIELDS: [&str; 2] = ["created_at", "has_tulip"];
+
+pub fn field_changes<T: Serialize>(before: &T, after: &T) -> Vec<FieldChange> {
+    let (Ok(Value::Object(before)), Ok(Value::Object(after))) = (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return Vec::new();
+    };
+
+    before
+        .iter()
+        .filter(|(field, _)| !IGNORED_FIELDS.contains(&field.as_str()))
+        .filter_map(|(field, value)| {
+            let new_value = after.get(field).cloned().unwrap_or(Value::Null);
+            (*value != new_value).then(|| FieldChange {
+                field: field.clone(),
+                before: value.clone(),
+                after: new_value,
+            })
+        })
+        .collect()
+}
+
+pub fn reference_changes(before: &PCSnapshot, after: &PCSnapshot) -> Vec<ReferenceChange> {
+    let find = |references: &[ReferenceEntry], id: i64| references.iter().find(|r| r.id == id).cloned();
+    let mut changes = Vec::new();
+
+    for reference in &after.references {
+        match find(&before.references, reference.id) {
+            Some(old) => {
+                let fields = field_changes(&old, reference);
+                if !fields.is_empty() {
+                    changes.push(ReferenceChange {
+                        reference_id: reference.id,
+                        status: "changed".to_string(),
+                        before: Some(old),
+                        after: Some(reference.clone()),
+                        fields,
+                    });
+                }
+            }
+            None => changes.push(ReferenceChange {
+                reference_id: reference.id,
+                status: "added".to_string(),
+                before: None,
+                after: Some(reference.clone()),
+                fields: Vec::new(),
+            }),
+        }
+    }
+
+    for reference in &before.references {
+        if find(&after.references, reference.id).is_none() {
+            changes.push(ReferenceChange {
+                reference_id: reference.id,
+                status: "removed".to_string(),
+                before: Some(reference.clone()),
+                after: None,
+                fields: Vec::new(),
+            });
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,409 @@
+use crate::march_table::{format_speed, format_time};
+use crate::models::{CreateReferenceRequest, ReferenceEntry};
+use std::collections::HashMap;
+
+// Roadbook tables (CSV or spreadsheet): one reference per line, with the PC
+// number it belongs to. Columns are found through a mapping from our field
+// names to the headers of the file, missing fields fall back to the usual
+// English and Spanish names. The time may be one column or split in parts.
+
+// Columns written on export
+pub const COLUMNS: [&str; 9] = [
+    "pc",
+    "time",
+    "event_type",
+    "speed",
+    "extra_value",
+    "defined_by",
+    "distance_meters",
+    "control_zone",
+    "notes",
+];
+
+const FIELDS: [&str; 13] = [
+    "pc",
+    "time",
+    "hours",
+    "minutes",
+    "seconds",
+    "centiseconds",
+    "event_type",
+    "speed",
+    "extra_value",
+    "defined_by",
+    "distance_meters",
+    "control_zone",
+    "notes",
+];
+
+// Data rows with their line number in the file, or why they could not be read
+pub type Records = Vec<(usize, Result<Vec<String>, String>)>;
+
+fn header_aliases(field: &str) -> &'static [&'static str] {
+    match field {
+        "pc" => &["pc", "pc_number", "numero pc", "nro pc"],
+        "time" => &["time", "hora", "horario", "tiempo"],
+        "hours" => &["hours", "hh", "horas"],
+        "minutes" => &["minutes", "mm", "minutos"],
+        "seconds" => &["seconds", "ss", "segundos"],
+        "centiseconds" => &["centiseconds", "cc", "centesimas", "centésimas"],
+        "event_type" => &["event_type", "event", "evento", "tipo"],
+        "speed" => &["speed", "velocidad", "vel", "km/h"],
+        "extra_value" => &["extra_value", "extra", "valor extra", "valor"],
+        "defined_by" => &["defined_by", "definido por"],
+        "distance_meters" => &["distance_meters", "distance", "distancia", "metros"],
+        "control_zone" => &["control_zone", "zc", "zona de control"],
+        "notes" => &["notes", "note", "notas", "nota", "observaciones"],
+        _ => &[],
+    }
+}
+
+// A reference read from the file, ready to be inserted
+#[derive(Debug, Clone)]
+pub struct RoadbookRow {
+    pub line: usize,
+    pub pc_number: i32,
+    pub time_centiseconds: i64,
+    pub event_type: String,
+    pub speed: i64,
+    pub extra_value: Option<f64>,
+    pub defined_by: Option<String>, // "time" or "distance", time when missing
+    pub distance_meters: Option<f64>,
+    pub is_control_zone: bool,
+    pub note: Option<String>,
+}
+
+impl RoadbookRow {
+    pub fn to_request(&self, pc_id: i64) -> CreateReferenceRequest {
+        CreateReferenceRequest {
+            pc_id,
+            hours: (self.time_centiseconds / 360000) as i32,
+            minutes: (self.time_centiseconds % 360000 / 6000) as i32,
+            seconds: (self.time_centiseconds % 6000 / 100) as i32,
+            centiseconds: (self.time_centiseconds % 100) as i32,
+            event_type: self.event_type.clone(),
+            speed: self.speed,
+            extra_value: self.extra_value,
+            defined_by: self.defined_by.clone(),
+            distance_meters: self.distance_meters,
+            note: self.note.clone(),
+            landmark: None,
+        }
+    }
+}
+
+// Resolves which column holds each field. Explicit entries win over aliases.
+pub fn resolve_mapping(
+    headers: &[String],
+    mapping: &HashMap<String, String>,
+) -> Result<HashMap<String, usize>, String> {
+    let normalized: Vec<String> = headers.iter().map(|h| h.trim().to_lowercase()).collect();
+    let mut columns = HashMap::new();
+
+    for field in FIELDS {
+        let index = match mapping.get(field) {
+            Some(header) => Some(
+                normalized
+                    .iter()
+                    .position(|h| *h == header.trim().to_lowercase())
+                    .ok_or_else(|| format!("Column \"{}\" mapped to {} is not in the file", header, field))?,
+            ),
+            None => header_aliases(field).iter().find_map(|alias| normalized.iter().position(|h| h == alias)),
+        };
+        if let Some(index) = index {
+            columns.insert(field.to_string(), index);
+        }
+    }
+
+    for required in ["pc", "event_type", "speed"] {
+        if !columns.contains_key(required) {
+            return Err(format!("No column found for {}", required));
+        }
+    }
+    let split_time = ["hours", "minutes", "seconds"].iter().all(|field| columns.contains_key(*field));
+    if !columns.contains_key("time") && !split_time {
+        return Err("No column found for time, map it whole or as hours, minutes and seconds".to_string());
+    }
+
+    Ok(columns)
+}
+
+// Accepts HH:MM:SS, HH:MM:SS.cc, HH:MM:SS,cc and HH:MM:SS:CC, as well as the
+// fraction of a day spreadsheets store times as
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    if let Some(fraction) = value.trim().parse::<f64>().ok().filter(|f| (0.0..1.0).contains(f)) {
+        return Ok((fraction * 8640000.0).round() as i64 % 8640000);
+    }
+
+    let parts: Vec<&str> = value.trim().split([':', '.', ',']).collect();
+    if parts.len() < 3 || parts.len() > 4 {
+        return Err(format!("Invalid time \"{}\"", value));
+    }
+
+    let mut numbers = Vec::with_capacity(4);
+    for part in &parts {
+        numbers.push(part.parse::<i64>().map_err(|_| format!("Invalid time \"{}\"", value))?);
+    }
+    let (hours, minutes, seconds) = (numbers[0], numbers[1], numbers[2]);
+    let centiseconds = match parts.get(3) {
+        // "5" after the separator means 50 centiseconds
+        Some(part) if part.len() == 1 => numbers[3] * 10,
+        Some(_) => numbers[3],
+        None => 0,
+    };
+
+    compose_time(hours, minutes, seconds, centiseconds).ok_or_else(|| format!("Time out of range \"{}\"", value))
+}
+
+fn compose_time(hours: i64, minutes: i64, seconds: i64, centiseconds: i64) -> Option<i64> {
+    let valid = (0..=23).contains(&hours)
+        && (0..=59).contains(&minutes)
+        && (0..=59).contains(&seconds)
+        && (0..=99).contains(&centiseconds);
+    valid.then_some(hours * 360000 + minutes * 6000 + seconds * 100 + centiseconds)
+}
+
+// Time given in separate hours, minutes, seconds and (optional) centiseconds columns
+fn parse_split_time(hours: &str, minutes: &str, seconds: &str, centiseconds: &str) -> Result<i64, String> {
+    let whole = |value: &str| match value.trim() {
+        "" => Some(0),
+        value => parse_decimal(value).filter(|n| n.fract() == 0.0).map(|n| n as i64),
+    };
+    let description = format!("{}:{}:{}.{}", hours, minutes, seconds, centiseconds);
+
+    match (whole(hours), whole(minutes), whole(seconds), whole(centiseconds)) {
+        (Some(h), Some(m), Some(s), Some(cc)) => {
+            compose_time(h, m, s, cc).ok_or_else(|| format!("Time out of range \"{}\"", description))
+        }
+        _ => Err(format!("Invalid time \"{}\"", description)),
+    }
+}
+
+pub fn parse_decimal(value: &str) -> Option<f64> {
+    value.trim().replace(',', ".").parse::<f64>().ok()
+}
+
+fn parse_flag(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "" | "0" | "no" | "n" | "false" => Ok(false),
+        "1" | "si" | "sí" | "s" | "x" | "zc" | "true" | "yes" => Ok(true),
+        other => Err(format!("Invalid control zone value \"{}\"", other)),
+    }
+}
+
+pub fn parse_record(line: usize, record: &[String], columns: &HashMap<String, usize>) -> Result<RoadbookRow, String> {
+    let field = |name: &str| columns.get(name).and_then(|&i| record.get(i)).map_or("", |value| value.trim());
+
+    let pc_number = field("pc")
+        .parse::<i32>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("Invalid PC number \"{}\"", field("pc")))?;
+    let time_centiseconds = if columns.contains_key("time") {
+        parse_time(field("time"))?
+    } else {
+        parse_split_time(field("hours"), field("minutes"), field("seconds"), field("centiseconds"))?
+    };
+
+    let event_type = field("event_type").to_uppercase();
+    if !matches!(event_type.as_str(), "LAR" | "REF" | "ADL" | "ATR" | "CVT" | "CVD" | "CVR") {
+        return Err(format!("Unknown event type \"{}\"", field("event_type")));
+    }
+
+    let speed = parse_decimal(field("speed"))
+        .filter(|speed| *speed >= 0.0)
+        .map(|speed| (speed * 1000.0).round() as i64)
+        .ok_or_else(|| format!("Invalid speed \"{}\"", field("speed")))?;
+
+    let extra_value = match field("extra_value") {
+        "" => None,
+        value => Some(parse_decimal(value).ok_or_else(|| format!("Invalid extra value \"{}\"", value))?),
+    };
+
+    let defined_by = match field("defined_by").to_lowercase().as_str() {
+        "" => None,
+        value @ ("time" | "distance") => Some(value.to_string()),
+        _ => return Err(format!("Invalid defined_by \"{}\", expected time or distance", field("defined_by"))),
+    };
+    let distance_meters = match field("distance_meters") {
+        "" => None,
+        value => Some(
+            parse_decimal(value)
+                .filter(|distance| *distance >= 0.0)
+                .ok_or_else(|| format!("Invalid distance \"{}\"", value))?,
+        ),
+    };
+
+    let note = Some(field("notes").to_string()).filter(|note| !note.is_empty());
+
+    Ok(RoadbookRow {
+        line,
+        pc_number,
+        time_centiseconds,
+        event_type,
+        speed,
+        extra_value,
+        defined_by,
+        distance_meters,
+        is_control_zone: parse_flag(field("control_zone"))?,
+        note,
+    })
+}
+
+// Spreadsheets saved with a comma decimal separator use semicolons between columns
+fn detect_delimiter(contents: &str) -> u8 {
+    let header = contents.lines().next().unwrap_or("");
+    if header.matches(';').count() > header.matches(',').count() {
+        b';'
+    } else {
+        b','
+    }
+}
+
+pub fn read_csv(path: &str) -> Result<(Vec<String>, Records), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(detect_delimiter(&contents))
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(String::from)
+        .collect();
+
+    let records = reader
+        .records()
+        .enumerate()
+        .map(|(index, record)| {
+            let line = record.as_ref().ok().and_then(|r| r.position()).map_or(index + 2, |p| p.line() as usize);
+            (line, record.map(|r| r.iter().map(String::from).collect()).map_err(|e| e.to_string()))
+        })
+        .collect();
+
+    Ok((headers, records))
+}
+
+pub fn write_csv(pcs: &[(i32, Vec<ReferenceEntry>)], path: &str) -> Result<(), String> {
+    let mut writer = csv::Writer::from_path(path).map_err(|e| e.to_string())?;
+    writer.write_record(COLUMNS).map_err(|e| e.to_string())?;
+
+    for (pc_number, references) in pcs {
+        for reference in references {
+            writer
+                .write_record([
+                    pc_number.to_string(),
+                    format_time(reference.time_centiseconds()),
+                    reference.event_type.clone(),
+                    format_speed(reference.speed),
+                    reference.extra_value.map(|v| v.to_string()).unwrap_or_default(),
+                    reference.defined_by.clone(),
+                    reference.distance_meters.map(|v| v.to_string()).unwrap_or_default(),
+                    if reference.is_control_zone { "1" } else { "0" }.to_string(),
+                    reference.note.clone().unwrap_or_default(),
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    writer.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::reference;
+
+    const TIME: i64 = 10 * 360000 + 5 * 6000 + 30 * 100;
+
+    #[test]
+    fn single_column_times() {
+        assert_eq!(parse_time("10:05:30"), Ok(TIME));
+        assert_eq!(parse_time(" 10:05:30 "), Ok(TIME));
+        assert_eq!(parse_time("10:05:30.25"), Ok(TIME + 25));
+        assert_eq!(parse_time("10:05:30,25"), Ok(TIME + 25));
+        assert_eq!(parse_time("10:05:30:25"), Ok(TIME + 25));
+        // A single digit after the separator is tenths
+        assert_eq!(parse_time("10:05:30.5"), Ok(TIME + 50));
+        assert_eq!(parse_time("0:00:00"), Ok(0));
+
+        assert!(parse_time("10:05").is_err());
+        assert!(parse_time("10:05:30.25.1").is_err());
+        assert!(parse_time("10:05:xx").is_err());
+        assert!(parse_time("24:00:00").is_err());
+        assert!(parse_time("10:60:00").is_err());
+        assert!(parse_time("10:05:30.100").is_err());
+    }
+
+    #[test]
+    fn day_fraction_times() {
+        assert_eq!(parse_time("0"), Ok(0));
+        assert_eq!(parse_time("0.5"), Ok(12 * 360000));
+        assert_eq!(parse_time(&(TIME as f64 / 8640000.0).to_string()), Ok(TIME));
+        // A whole day or more is not a time of day
+        assert!(parse_time("1").is_err());
+        assert!(parse_time("-0.5").is_err());
+    }
+
+    #[test]
+    fn split_column_times() {
+        assert_eq!(parse_split_time("10", "5", "30", ""), Ok(TIME));
+        assert_eq!(parse_split_time("10", "05", "30", "25"), Ok(TIME + 25));
+        // Spreadsheets hand whole numbers over as decimals
+        assert_eq!(parse_split_time("10.0", "5", "30,0", ""), Ok(TIME));
+        assert_eq!(parse_split_time("", "", "", ""), Ok(0));
+
+        assert!(parse_split_time("10", "5.5", "30", "").is_err());
+        assert!(parse_split_time("10", "x", "30", "").is_err());
+        assert!(parse_split_time("10", "5", "60", "").is_err());
+    }
+
+    #[test]
+    fn exported_rows_read_back() {
+        let mut cvd = reference(2, "CVD", TIME + 6000, 72000);
+        cvd.defined_by = "distance".to_string();
+        cvd.distance_meters = Some(1250.5);
+        cvd.extra_value = Some(1.25);
+        let path = std::env::temp_dir().join("kiroshi_roadbook_test.csv").to_string_lossy().to_string();
+        write_csv(&[(3, vec![reference(1, "LAR", TIME, 36000), cvd])], &path).unwrap();
+
+        let (headers, records) = read_csv(&path).unwrap();
+        let columns = resolve_mapping(&headers, &HashMap::new()).unwrap();
+        let rows: Vec<RoadbookRow> = records
+            .into_iter()
+            .map(|(line, record)| parse_record(line, &record.unwrap(), &columns).unwrap())
+            .collect();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(rows[1].time_centiseconds, TIME + 6000);
+        assert_eq!(rows[0].defined_by.as_deref(), Some("time"));
+        assert_eq!(rows[0].distance_meters, None);
+        let request = rows[1].to_request(1);
+        assert_eq!((request.event_type.as_str(), request.speed), ("CVD", 72000));
+        assert_eq!(request.defined_by.as_deref(), Some("distance"));
+        assert_eq!(request.distance_meters, Some(1250.5));
+        assert_eq!(request.extra_value, Some(1.25));
+    }
+
+    #[test]
+    fn definition_columns_are_checked() {
+        let headers: Vec<String> = ["pc", "time", "event_type", "speed", "defined_by", "distance_meters"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        let columns = resolve_mapping(&headers, &HashMap::new()).unwrap();
+        let record = |defined_by: &str, distance: &str| -> Vec<String> {
+            ["1", "10:00:00", "REF", "45", defined_by, distance].iter().map(|v| v.to_string()).collect()
+        };
+
+        let row = parse_record(2, &record("", ""), &columns).unwrap();
+        assert_eq!((row.defined_by, row.distance_meters), (None, None));
+        let row = parse_record(2, &record("Distance", "1500,5"), &columns).unwrap();
+        assert_eq!((row.defined_by.as_deref(), row.distance_meters), (Some("distance"), Some(1500.5)));
+        assert!(parse_record(2, &record("km", ""), &columns).is_err());
+        assert!(parse_record(2, &record("distance", "-3"), &columns).is_err());
+    }
+}
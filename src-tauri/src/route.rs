@@ -0,0 +1,180 @@
+use crate::models::{ReferenceEntry, RouteInterval};
+
+// Ideal route of a PC: where and when the car should be at every reference.
+//
+// Between two references the car drives at the speed of the earlier one (LAR
+// sets the first speed, CVT/CVD/CVR set the following ones). ADL advances the
+// ideal clock by `extra_value` seconds that are not driven, ATR delays it by
+// `extra_value` seconds that are driven without showing on the clock.
+// References defined by time get their distance from the clock, references
+// defined by distance get their passage time from the distance.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutePoint {
+    pub reference_id: i64,
+    pub time_centiseconds: i64,
+    pub distance_meters: f64,
+    // Speed driven from this reference on, thousandths of km/h
+    pub speed: i64,
+}
+
+// Meters covered in one centisecond at a speed in thousandths of km/h
+fn meters_per_centisecond(speed: i64) -> f64 {
+    speed as f64 / 360000.0
+}
+
+// Clock shift introduced at a reference, in centiseconds
+pub fn time_shift_centiseconds(reference: &ReferenceEntry) -> i64 {
+    let seconds = reference.extra_value.unwrap_or(0.0);
+    match reference.event_type.as_str() {
+        "ADL" => (seconds * 100.0).round() as i64,
+        "ATR" => -(seconds * 100.0).round() as i64,
+        _ => 0,
+    }
+}
+
+pub fn compile(references: &[ReferenceEntry]) -> Vec<RoutePoint> {
+    let mut points: Vec<RoutePoint> = Vec::with_capacity(references.len());
+
+    for reference in references {
+        let point = match points.last() {
+            // The LAR is the origin of the route
+            None => RoutePoint {
+                reference_id: reference.id,
+                time_centiseconds: reference.time_centiseconds(),
+                distance_meters: 0.0,
+                speed: reference.speed,
+            },
+            Some(previous) => {
+                let rate = meters_per_centisecond(previous.speed);
+                let shift = time_shift_centiseconds(reference);
+
+                match reference.distance_meters.filter(|_| reference.defined_by == "distance") {
+                    Some(distance) if rate > 0.0 => {
+                        let driven = (((distance - previous.distance_meters) / rate).round() as i64).max(0);
+                        RoutePoint {
+                            reference_id: reference.id,
+                            time_centiseconds: previous.time_centiseconds + driven + shift,
+                            distance_meters: distance,
+                            speed: reference.speed,
+                        }
+                    }
+                    _ => {
+                        let time = reference.time_centiseconds();
+                        let driven = (time - previous.time_centiseconds - shift).max(0);
+                        RoutePoint {
+                            reference_id: reference.id,
+                            time_centiseconds: time,
+                            distance_meters: previous.distance_meters + driven as f64 * rate,
+                            speed: reference.speed,
+                        }
+                    }
+                }
+            }
+        };
+        points.push(point);
+    }
+
+    points
+}
+
+// Pairs every reference with the interval driven since the previous one
+pub fn intervals(references: &[ReferenceEntry]) -> Vec<RouteInterval> {
+    let points = compile(references);
+
+    references
+        .iter()
+        .zip(&points)
+        .enumerate()
+        .map(|(index, (reference, point))| {
+            let previous = index.checked_sub(1).map(|i| &points[i]);
+            RouteInterval {
+                reference_id: reference.id,
+                event_type: reference.event_type.clone(),
+                time_centiseconds: point.time_centiseconds,
+                distance_meters: point.distance_meters,
+                delta_centiseconds: previous.map(|p| point.time_centiseconds - p.time_centiseconds),
+                delta_meters: previous.map(|p| point.distance_meters - p.distance_meters),
+                segment_speed: previous.map_or(point.speed, |p| p.speed),
+                time_shift_centiseconds: time_shift_centiseconds(reference),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{reference, TEN};
+
+    fn at_distance(id: i64, event_type: &str, distance: f64, speed: i64) -> ReferenceEntry {
+        let mut reference = reference(id, event_type, 0, speed);
+        reference.defined_by = "distance".to_string();
+        reference.distance_meters = Some(distance);
+        reference
+    }
+
+    fn shifted(id: i64, event_type: &str, time: i64, seconds: f64) -> ReferenceEntry {
+        let mut reference = reference(id, event_type, time, 36000);
+        reference.extra_value = Some(seconds);
+        reference
+    }
+
+    #[test]
+    fn time_defined_references_get_their_distance() {
+        let points = compile(&[
+            reference(1, "LAR", TEN, 36000),
+            reference(2, "CVT", TEN + 6000, 72000),
+            reference(3, "REF", TEN + 9000, 72000),
+        ]);
+        assert_eq!(points[0].distance_meters, 0.0);
+        assert_eq!(points[1].distance_meters, 600.0);
+        // After the CVT the segment is driven at 72 km/h
+        assert_eq!(points[2].distance_meters, 1200.0);
+    }
+
+    #[test]
+    fn adl_and_atr_shift_the_clock() {
+        assert_eq!(time_shift_centiseconds(&shifted(2, "ADL", 0, 10.0)), 1000);
+        assert_eq!(time_shift_centiseconds(&shifted(2, "ATR", 0, 10.0)), -1000);
+        assert_eq!(time_shift_centiseconds(&shifted(2, "REF", 0, 10.0)), 0);
+
+        // Both reach 600 m: the ADL shows 10 s more on the clock, the ATR 10 s less
+        let adl = compile(&[reference(1, "LAR", TEN, 36000), shifted(2, "ADL", TEN + 7000, 10.0)]);
+        assert_eq!(adl[1].distance_meters, 600.0);
+        let atr = compile(&[reference(1, "LAR", TEN, 36000), shifted(2, "ATR", TEN + 5000, 10.0)]);
+        assert_eq!(atr[1].distance_meters, 600.0);
+    }
+
+    #[test]
+    fn distance_defined_references_get_their_time() {
+        let points = compile(&[
+            reference(1, "LAR", TEN, 36000),
+            at_distance(2, "CVD", 1000.0, 72000),
+            at_distance(3, "REF", 1600.0, 72000),
+        ]);
+        assert_eq!(points[1].time_centiseconds, TEN + 10000);
+        assert_eq!(points[2].time_centiseconds, TEN + 13000);
+        assert_eq!(points[2].distance_meters, 1600.0);
+
+        // An ADL on a distance-defined reference adds its seconds to the computed time
+        let mut adl = at_distance(2, "ADL", 1000.0, 36000);
+        adl.extra_value = Some(10.0);
+        let points = compile(&[reference(1, "LAR", TEN, 36000), adl]);
+        assert_eq!(points[1].time_centiseconds, TEN + 11000);
+    }
+
+    #[test]
+    fn backward_distances_never_move_the_clock_back() {
+        let points = compile(&[
+            reference(1, "LAR", TEN, 36000),
+            at_distance(2, "REF", 1000.0, 36000),
+            at_distance(3, "REF", 500.0, 36000),
+        ]);
+        assert_eq!(points[2].time_centiseconds, points[1].time_centiseconds);
+
+        // A time before the previous one does not drive backwards either
+        let points = compile(&[reference(1, "LAR", TEN, 36000), reference(2, "REF", TEN - 1000, 36000)]);
+        assert_eq!(points[1].distance_meters, 0.0);
+    }
+}
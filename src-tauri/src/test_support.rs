@@ -0,0 +1,32 @@
+use crate::models::ReferenceEntry;
+
+// Builders shared by the unit tests
+
+// 10:00:00, where the test routes start
+pub const TEN: i64 = 10 * 360000;
+
+// Time-defined reference of PC 1. At 36 km/h (36000) the car drives 10 m/s,
+// 0.1 m per centisecond, which keeps the expected distances round.
+pub fn reference(id: i64, event_type: &str, time: i64, speed: i64) -> ReferenceEntry {
+    let mut reference = ReferenceEntry {
+        id,
+        pc_id: 1,
+        hours: 0,
+        minutes: 0,
+        seconds: 0,
+        centiseconds: 0,
+        event_type: event_type.to_string(),
+        speed,
+        extra_value: None,
+        is_control_zone: false,
+        order_index: id as i32,
+        defined_by: "time".to_string(),
+        distance_meters: None,
+        note: None,
+        landmark: None,
+        has_tulip: false,
+        created_at: String::new(),
+    };
+    reference.set_time_centiseconds(time);
+    reference
+}
@@ -0,0 +1,151 @@
+use crate::models::{PC, ReferenceEntry, ValidationIssue, ValidationReport};
+use crate::route;
+
+// Structural and timing rules for the references of a single PC (Hoja de Ruta).
+// Errors make the roadbook unusable in race mode, warnings are suspicious but allowed.
+
+struct IssueList {
+    pc_id: i64,
+    issues: Vec<ValidationIssue>,
+}
+
+impl IssueList {
+    fn push(&mut self, reference_id: Option<i64>, severity: &str, code: &str, message: String) {
+        self.issues.push(ValidationIssue {
+            pc_id: self.pc_id,
+            reference_id,
+            severity: severity.to_string(),
+            code: code.to_string(),
+            message,
+        });
+    }
+
+    fn error(&mut self, reference_id: Option<i64>, code: &str, message: String) {
+        self.push(reference_id, "error", code, message);
+    }
+
+    fn warning(&mut self, reference_id: Option<i64>, code: &str, message: String) {
+        self.push(reference_id, "warning", code, message);
+    }
+}
+
+pub fn validate_references(pc: &PC, references: &[ReferenceEntry]) -> Vec<ValidationIssue> {
+    let mut issues = IssueList {
+        pc_id: pc.id,
+        issues: Vec::new(),
+    };
+
+    // A liaison is driven against a target time, not a reference list
+    if pc.pc_type == "liaison" {
+        if pc.target_time_centiseconds.is_none() {
+            issues.error(None, "liaison_missing_target", "El enlace no tiene tiempo objetivo".to_string());
+        }
+        if !references.is_empty() {
+            issues.warning(None, "liaison_with_references", "El enlace tiene referencias, no se usan en carrera".to_string());
+        }
+        return issues.issues;
+    }
+
+    if references.is_empty() {
+        issues.warning(None, "empty_pc", "El PC no tiene referencias".to_string());
+        return issues.issues;
+    }
+
+    let points = route::compile(references);
+    let mut last_cvd_distance: Option<f64> = None;
+
+    for (index, reference) in references.iter().enumerate() {
+        let id = Some(reference.id);
+        let n = index + 1;
+
+        // Time components
+        if !(0..=23).contains(&reference.hours) {
+            issues.error(id, "invalid_hours", format!("Referencia {}: horas fuera de rango ({})", n, reference.hours));
+        }
+        if !(0..=59).contains(&reference.minutes) {
+            issues.error(id, "invalid_minutes", format!("Referencia {}: minutos fuera de rango ({})", n, reference.minutes));
+        }
+        if !(0..=59).contains(&reference.seconds) {
+            issues.error(id, "invalid_seconds", format!("Referencia {}: segundos fuera de rango ({})", n, reference.seconds));
+        }
+        if !(0..=99).contains(&reference.centiseconds) {
+            issues.error(id, "invalid_centiseconds", format!("Referencia {}: centésimas fuera de rango ({})", n, reference.centiseconds));
+        }
+
+        if reference.speed <= 0 {
+            issues.error(id, "invalid_speed", format!("Referencia {}: la velocidad debe ser mayor a cero", n));
+        }
+
+        // A PC starts with exactly one LAR
+        if index == 0 && reference.event_type != "LAR" {
+            issues.error(id, "first_not_lar", format!("La primera referencia debe ser LAR, no {}", reference.event_type));
+        } else if index > 0 && reference.event_type == "LAR" {
+            issues.error(id, "duplicate_lar", format!("Referencia {}: LAR solo puede ser la primera referencia", n));
+        }
+
+        // Event specific values
+        match reference.event_type.as_str() {
+            "CVD" => match reference.extra_value {
+                Some(distance) if distance > 0.0 => {
+                    if let Some(previous) = last_cvd_distance.filter(|previous| distance <= *previous) {
+                        issues.warning(id, "cvd_distance_not_increasing", format!("Referencia {}: el CVD ({} km) no supera la distancia del CVD anterior ({} km)", n, distance, previous));
+                    }
+                    last_cvd_distance = Some(distance);
+                }
+                _ => issues.error(id, "cvd_missing_distance", format!("Referencia {}: el CVD no tiene distancia", n)),
+            },
+            "ADL" | "ATR" => {
+                if !matches!(reference.extra_value, Some(seconds) if seconds > 0.0) {
+                    issues.error(id, "missing_time_shift", format!("Referencia {}: el {} no tiene segundos", n, reference.event_type));
+                }
+            }
+            _ => {
+                if reference.extra_value.is_some() {
+                    issues.warning(id, "unused_extra_value", format!("Referencia {}: el valor extra no se usa en un {}", n, reference.event_type));
+                }
+            }
+        }
+
+        if index == 0 {
+            continue;
+        }
+        let previous = &references[index - 1];
+
+        // Passage times must strictly increase
+        if reference.time_centiseconds() <= previous.time_centiseconds() {
+            issues.error(id, "time_not_increasing", format!("Referencia {}: el horario no es posterior al anterior", n));
+        }
+
+        // A distance must lie ahead of the previous reference on the route
+        if reference.defined_by == "distance" {
+            let previous_distance = points[index - 1].distance_meters;
+            if let Some(distance) = reference.distance_meters {
+                if distance <= previous_distance {
+                    issues.error(id, "distance_not_increasing", format!("Referencia {}: la distancia ({:.0} m) no supera la de la referencia anterior ({:.0} m)", n, distance, previous_distance));
+                }
+            }
+        }
+
+        // Speed may only change on CVT, CVD or CVR
+        if reference.is_speed_change() {
+            if reference.speed == previous.speed {
+                issues.warning(id, "redundant_speed_change", format!("Referencia {}: el {} no cambia la velocidad", n, reference.event_type));
+            }
+        } else if reference.speed != previous.speed {
+            issues.error(id, "speed_change_without_event", format!("Referencia {}: la velocidad cambia en un {}", n, reference.event_type));
+        }
+    }
+
+    issues.issues
+}
+
+pub fn build_report(pc_id: i64, pc_number: i32, issues: Vec<ValidationIssue>) -> ValidationReport {
+    let error_count = issues.iter().filter(|i| i.severity == "error").count();
+    ValidationReport {
+        pc_id,
+        pc_number,
+        error_count,
+        warning_count: issues.len() - error_count,
+        issues,
+    }
+}
@@ -0,0 +1,87 @@
+use crate::models::XlsxSheetPreview;
+use crate::roadbook::Records;
+use calamine::{open_workbook_auto, Data, Range, Reader};
+
+// Spreadsheet side of the roadbook import: cells are turned into the same
+// text the CSV importer reads, so both share mapping and validation.
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty | Data::Error(_) => String::new(),
+        Data::String(value) | Data::DateTimeIso(value) | Data::DurationIso(value) => value.trim().to_string(),
+        Data::Int(value) => value.to_string(),
+        Data::Float(value) if value.fract() == 0.0 => format!("{}", *value as i64),
+        Data::Float(value) => value.to_string(),
+        Data::Bool(value) => if *value { "1" } else { "0" }.to_string(),
+        // Time cells keep only the time of day, as HH:MM:SS.cc
+        Data::DateTime(value) => {
+            let centiseconds = (value.as_f64().fract() * 8640000.0).round() as i64 % 8640000;
+            format!(
+                "{:02}:{:02}:{:02}.{:02}",
+                centiseconds / 360000,
+                centiseconds % 360000 / 6000,
+                centiseconds % 6000 / 100,
+                centiseconds % 100
+            )
+        }
+    }
+}
+
+fn open_sheet(path: &str, sheet_name: Option<&str>) -> Result<Range<Data>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+    let name = match sheet_name {
+        Some(name) => name.to_string(),
+        None => workbook
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or_else(|| "The workbook has no sheets".to_string())?,
+    };
+    workbook.worksheet_range(&name).map_err(|e| format!("Sheet \"{}\": {}", name, e))
+}
+
+// First rows of every sheet, to pick the sheet, header row and columns
+pub fn preview_sheets(path: &str, max_rows: usize) -> Result<Vec<XlsxSheetPreview>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+
+    workbook
+        .sheet_names()
+        .into_iter()
+        .map(|name| {
+            let range = workbook.worksheet_range(&name).map_err(|e| e.to_string())?;
+            let rows = range
+                .rows()
+                .take(max_rows)
+                .map(|row| row.iter().map(cell_text).collect())
+                .collect();
+            Ok(XlsxSheetPreview { name, rows })
+        })
+        .collect()
+}
+
+// Reads a sheet as headers plus data rows, numbered as in the spreadsheet.
+// `header_row` is the 1-based sheet row holding the headers, by default the
+// first row in use. Empty rows are skipped.
+pub fn read_sheet(path: &str, sheet_name: Option<&str>, header_row: Option<usize>) -> Result<(Vec<String>, Records), String> {
+    let range = open_sheet(path, sheet_name)?;
+    let first_row = range.start().map_or(0, |(row, _)| row as usize);
+    let header_index = match header_row {
+        Some(row) if row > first_row => row - 1 - first_row,
+        Some(row) => return Err(format!("Row {} of the sheet is empty", row)),
+        None => 0,
+    };
+
+    let mut rows = range.rows().enumerate().skip(header_index);
+    let headers = rows
+        .next()
+        .map(|(_, row)| row.iter().map(cell_text).collect())
+        .ok_or_else(|| "The sheet has no header row".to_string())?;
+
+    let records = rows
+        .map(|(index, row)| (first_row + index + 1, row.iter().map(cell_text).collect::<Vec<_>>()))
+        .filter(|(_, cells)| cells.iter().any(|cell| !cell.is_empty()))
+        .map(|(line, cells)| (line, Ok(cells)))
+        .collect();
+
+    Ok((headers, records))
+}
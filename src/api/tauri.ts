@@ -1,27 +1,83 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
   Race,
+  RaceLockEvent,
+  CommandError,
+  CreateRaceRequest,
+  UpdateRaceRequest,
+  RaceSort,
   PC,
+  PCType,
+  UpdatePCRequest,
   ReferenceEntry,
   CreateReferenceRequest,
   UpdateReferenceRequest,
+  ReferenceTulip,
   RaceTimerState,
+  ValidationReport,
+  PCChecksum,
+  RaceChecksum,
+  PCChecksumComparison,
+  DuplicateOptions,
+  RouteInterval,
+  MarchTableOptions,
+  MarchRow,
+  RoadbookMapping,
+  XlsxSheetPreview,
+  XlsxImportOptions,
+  ImportTemplate,
+  SaveImportTemplateRequest,
+  PdfImportOptions,
+  PdfImportPreview,
+  Revision,
+  PCSnapshot,
+  PCRevisionDiff,
+  Bulletin,
+  SaveBulletinRequest,
+  BulletinPreview,
+  EditHistoryEntry,
+  TrashItem,
+  RoadbookImportReport,
+  RaceBundleImportMode,
+  RaceBundleImportReport,
+  QrChunk,
+  QrTransferStatus,
+  BackupInfo,
+  BackupRace,
+  IntegrityReport,
 } from "../types";
 
 // ==================== RACE API ====================
 
-export const getAllRaces = () => invoke<Race[]>("get_all_races");
+export const getAllRaces = (search?: string, sort?: RaceSort) =>
+  invoke<Race[]>("get_all_races", { search, sort });
 
 export const getRace = (id: number) => invoke<Race>("get_race", { id });
 
-export const createRace = (name: string) =>
-  invoke<Race>("create_race", { name });
+export const createRace = (request: CreateRaceRequest) =>
+  invoke<Race>("create_race", { request });
 
-export const updateRace = (id: number, name: string) =>
-  invoke<Race>("update_race", { id, name });
+export const updateRace = (request: UpdateRaceRequest) =>
+  invoke<Race>("update_race", { request });
 
 export const deleteRace = (id: number) => invoke<void>("delete_race", { id });
 
+// ==================== RACE LOCK API ====================
+
+export const lockRace = (raceId: number, reason?: string) =>
+  invoke<Race>("lock_race", { raceId, reason });
+
+export const unlockRace = (raceId: number, reason?: string) =>
+  invoke<Race>("unlock_race", { raceId, reason });
+
+export const getRaceLockEvents = (raceId: number) =>
+  invoke<RaceLockEvent[]>("get_race_lock_events", { raceId });
+
+export const isRaceLockedError = (error: unknown): error is CommandError =>
+  typeof error === "object" &&
+  error !== null &&
+  (error as CommandError).kind === "race_locked";
+
 // ==================== PC API ====================
 
 export const getPcsByRace = (raceId: number) =>
@@ -32,6 +88,9 @@ export const getPc = (id: number) => invoke<PC>("get_pc", { id });
 export const createPc = (raceId: number) =>
   invoke<PC>("create_pc", { raceId });
 
+export const updatePc = (request: UpdatePCRequest) =>
+  invoke<PC>("update_pc", { request });
+
 export const deletePc = (id: number) => invoke<void>("delete_pc", { id });
 
 export const getNextPc = (pcId: number) =>
@@ -40,6 +99,17 @@ export const getNextPc = (pcId: number) =>
 export const createNextPc = (currentPcId: number) =>
   invoke<PC>("create_next_pc", { currentPcId });
 
+// ==================== PC NUMBERING API ====================
+
+export const insertPcAt = (raceId: number, pcNumber: number) =>
+  invoke<PC>("insert_pc_at", { raceId, pcNumber });
+
+export const renumberPcs = (raceId: number) =>
+  invoke<PC[]>("renumber_pcs", { raceId });
+
+export const movePc = (id: number, newNumber: number) =>
+  invoke<PC[]>("move_pc", { id, newNumber });
+
 // ==================== REFERENCE API ====================
 
 export const getReferencesByPc = (pcId: number) =>
@@ -48,15 +118,331 @@ export const getReferencesByPc = (pcId: number) =>
 export const createReference = (request: CreateReferenceRequest) =>
   invoke<ReferenceEntry>("create_reference", { request });
 
+// A new speed on a LAR or speed change carries over to the rest of its segment
 export const updateReference = (request: UpdateReferenceRequest) =>
   invoke<ReferenceEntry>("update_reference", { request });
 
+export const setSegmentSpeed = (id: number, speed: number) =>
+  invoke<ReferenceEntry[]>("set_segment_speed", { id, speed });
+
 export const deleteReference = (id: number) =>
   invoke<void>("delete_reference", { id });
 
 export const toggleControlZone = (id: number) =>
   invoke<ReferenceEntry>("toggle_control_zone", { id });
 
+// ==================== TULIP API ====================
+
+export const getReferenceTulip = (referenceId: number) =>
+  invoke<ReferenceTulip | null>("get_reference_tulip", { referenceId });
+
+export const setReferenceTulip = (
+  referenceId: number,
+  mimeType: string,
+  data: number[],
+  description?: string | null
+) =>
+  invoke<ReferenceTulip>("set_reference_tulip", {
+    referenceId,
+    mimeType,
+    data,
+    description,
+  });
+
+export const deleteReferenceTulip = (referenceId: number) =>
+  invoke<void>("delete_reference_tulip", { referenceId });
+
+// ==================== REFERENCE ORDER API ====================
+
+export const insertReferenceAt = (
+  request: CreateReferenceRequest,
+  position: number
+) =>
+  invoke<ReferenceEntry[]>("insert_reference_at", { request, position });
+
+export const moveReference = (id: number, newIndex: number) =>
+  invoke<ReferenceEntry[]>("move_reference", { id, newIndex });
+
+export const moveReferenceUp = (id: number) =>
+  invoke<ReferenceEntry[]>("move_reference_up", { id });
+
+export const moveReferenceDown = (id: number) =>
+  invoke<ReferenceEntry[]>("move_reference_down", { id });
+
+export const compactReferenceOrder = (pcId: number) =>
+  invoke<ReferenceEntry[]>("compact_reference_order", { pcId });
+
+// ==================== DUPLICATE API ====================
+
+export const duplicateRace = (
+  id: number,
+  name?: string,
+  options?: DuplicateOptions
+) => invoke<Race>("duplicate_race", { id, name, options });
+
+export const duplicatePc = (
+  id: number,
+  targetRaceId?: number,
+  options?: DuplicateOptions
+) => invoke<PC>("duplicate_pc", { id, targetRaceId, options });
+
+// ==================== ROUTE API ====================
+
+export const getRouteIntervals = (pcId: number) =>
+  invoke<RouteInterval[]>("get_route_intervals", { pcId });
+
+// ==================== MARCH TABLE API ====================
+
+export const getMarchTable = (pcId: number, options: MarchTableOptions) =>
+  invoke<MarchRow[]>("get_march_table", { pcId, options });
+
+export const exportMarchTableCsv = (
+  pcId: number,
+  options: MarchTableOptions,
+  path: string
+) => invoke<void>("export_march_table_csv", { pcId, options, path });
+
+export const exportMarchTablePdf = (
+  pcId: number,
+  options: MarchTableOptions,
+  path: string
+) => invoke<void>("export_march_table_pdf", { pcId, options, path });
+
+// ==================== VALIDATION API ====================
+
+export const validatePc = (pcId: number) =>
+  invoke<ValidationReport>("validate_pc", { pcId });
+
+export const validateRace = (raceId: number) =>
+  invoke<ValidationReport[]>("validate_race", { raceId });
+
+// ==================== CHECKSUM API ====================
+
+export const getRaceChecksum = (raceId: number) =>
+  invoke<RaceChecksum>("get_race_checksum", { raceId });
+
+export const getPcChecksum = (pcId: number) =>
+  invoke<PCChecksum>("get_pc_checksum", { pcId });
+
+// `other` holds the fingerprints of the other machine by PC number
+export const compareRaceChecksum = (
+  raceId: number,
+  other: Record<number, string>
+) =>
+  invoke<PCChecksumComparison[]>("compare_race_checksum", { raceId, other });
+
+// ==================== ROADBOOK API ====================
+
+export const exportRoadbookCsv = (
+  raceId: number,
+  pcId: number | null,
+  path: string
+) => invoke<void>("export_roadbook_csv", { raceId, pcId, path });
+
+export const previewRoadbookImport = (
+  raceId: number,
+  path: string,
+  mapping?: RoadbookMapping,
+  replaceExisting?: boolean
+) =>
+  invoke<RoadbookImportReport>("preview_roadbook_import", {
+    raceId,
+    path,
+    mapping,
+    replaceExisting,
+  });
+
+export const importRoadbookCsv = (
+  raceId: number,
+  path: string,
+  mapping?: RoadbookMapping,
+  replaceExisting?: boolean
+) =>
+  invoke<RoadbookImportReport>("import_roadbook_csv", {
+    raceId,
+    path,
+    mapping,
+    replaceExisting,
+  });
+
+// ==================== XLSX IMPORT API ====================
+
+export const getXlsxSheets = (path: string) =>
+  invoke<XlsxSheetPreview[]>("get_xlsx_sheets", { path });
+
+export const previewXlsxImport = (
+  raceId: number,
+  path: string,
+  options: XlsxImportOptions
+) =>
+  invoke<RoadbookImportReport>("preview_xlsx_import", {
+    raceId,
+    path,
+    options,
+  });
+
+export const importRoadbookXlsx = (
+  raceId: number,
+  path: string,
+  options: XlsxImportOptions
+) =>
+  invoke<RoadbookImportReport>("import_roadbook_xlsx", {
+    raceId,
+    path,
+    options,
+  });
+
+export const getImportTemplates = (organizer?: string) =>
+  invoke<ImportTemplate[]>("get_import_templates", { organizer });
+
+export const saveImportTemplate = (request: SaveImportTemplateRequest) =>
+  invoke<ImportTemplate>("save_import_template", { request });
+
+export const deleteImportTemplate = (id: number) =>
+  invoke<void>("delete_import_template", { id });
+
+// ==================== PDF IMPORT API ====================
+
+export const previewPdfImport = (
+  raceId: number,
+  path: string,
+  options: PdfImportOptions
+) =>
+  invoke<PdfImportPreview>("preview_pdf_import", { raceId, path, options });
+
+export const importPdfReferences = (
+  raceId: number,
+  path: string,
+  options: PdfImportOptions
+) =>
+  invoke<RoadbookImportReport>("import_pdf_references", {
+    raceId,
+    path,
+    options,
+  });
+
+// ==================== BUNDLE API ====================
+
+export const exportRaceBundle = (raceId: number, path: string) =>
+  invoke<void>("export_race_bundle", { raceId, path });
+
+export const importRaceBundle = (path: string, mode?: RaceBundleImportMode) =>
+  invoke<RaceBundleImportReport>("import_race_bundle", { path, mode });
+
+// ==================== QR TRANSFER API ====================
+
+export const exportRaceQr = (raceId: number, chunkSize?: number) =>
+  invoke<QrChunk[]>("export_race_qr", { raceId, chunkSize });
+
+// Payloads scanned so far, in any order and with repeats
+export const readRaceQr = (payloads: string[]) =>
+  invoke<QrTransferStatus>("read_race_qr", { payloads });
+
+export const importRaceQr = (
+  payloads: string[],
+  mode?: RaceBundleImportMode
+) => invoke<RaceBundleImportReport>("import_race_qr", { payloads, mode });
+
+// ==================== BACKUP API ====================
+
+export const createBackup = () => invoke<BackupInfo>("create_backup");
+
+// Newest first
+export const getBackups = () => invoke<BackupInfo[]>("get_backups");
+
+export const getBackupRaces = (fileName: string) =>
+  invoke<BackupRace[]>("get_backup_races", { fileName });
+
+// Replaces the whole database; the current one is backed up first
+export const restoreBackup = (fileName: string) =>
+  invoke<void>("restore_backup", { fileName });
+
+export const extractBackupRace = (
+  fileName: string,
+  raceId: number,
+  mode?: RaceBundleImportMode
+) =>
+  invoke<RaceBundleImportReport>("extract_backup_race", {
+    fileName,
+    raceId,
+    mode,
+  });
+
+// ==================== INTEGRITY API ====================
+
+export const checkDatabase = () => invoke<IntegrityReport>("check_database");
+
+// Rejects when the file itself is damaged, which takes a backup restore
+export const repairDatabase = () =>
+  invoke<IntegrityReport>("repair_database");
+
+// ==================== REVISION API ====================
+
+export const getRevisions = (raceId: number, pcId?: number) =>
+  invoke<Revision[]>("get_revisions", { raceId, pcId });
+
+export const getPcRevision = (id: number) =>
+  invoke<PCSnapshot>("get_pc_revision", { id });
+
+export const diffPcRevisions = (fromId: number, toId?: number) =>
+  invoke<PCRevisionDiff>("diff_pc_revisions", { fromId, toId });
+
+export const restoreRevision = (id: number) =>
+  invoke<Revision>("restore_revision", { id });
+
+// ==================== UNDO API ====================
+
+// Resolve to the edit undone or redone, null when there was none
+export const undoEdit = () => invoke<EditHistoryEntry | null>("undo_edit");
+
+export const redoEdit = () => invoke<EditHistoryEntry | null>("redo_edit");
+
+export const getEditHistory = (limit?: number) =>
+  invoke<EditHistoryEntry[]>("get_edit_history", { limit });
+
+// ==================== TRASH API ====================
+
+export const getTrash = () => invoke<TrashItem[]>("get_trash");
+
+export const restoreRace = (id: number) => invoke<Race>("restore_race", { id });
+
+// Resolves to the PCs of the race, renumbered if the PC number was taken
+export const restorePc = (id: number) => invoke<PC[]>("restore_pc", { id });
+
+export const purgeRace = (id: number) => invoke<void>("purge_race", { id });
+
+export const purgePc = (id: number) => invoke<void>("purge_pc", { id });
+
+// Resolves to how many races and PCs were deleted
+export const emptyTrash = () => invoke<number>("empty_trash");
+
+export const getTrashRetentionDays = () =>
+  invoke<number>("get_trash_retention_days");
+
+// 0 keeps everything until the trash is emptied by hand
+export const setTrashRetentionDays = (days: number) =>
+  invoke<void>("set_trash_retention_days", { days });
+
+// ==================== BULLETIN API ====================
+
+export const getBulletins = (raceId: number) =>
+  invoke<Bulletin[]>("get_bulletins", { raceId });
+
+export const saveBulletin = (request: SaveBulletinRequest) =>
+  invoke<Bulletin>("save_bulletin", { request });
+
+export const deleteBulletin = (id: number) =>
+  invoke<void>("delete_bulletin", { id });
+
+export const previewBulletin = (id: number) =>
+  invoke<BulletinPreview>("preview_bulletin", { id });
+
+export const applyBulletin = (id: number) =>
+  invoke<BulletinPreview>("apply_bulletin", { id });
+
+export const revertBulletin = (id: number, force?: boolean) =>
+  invoke<Bulletin>("revert_bulletin", { id, force });
+
 // ==================== PREFERENCE API ====================
 
 export const getPreference = (key: string) =>
@@ -105,3 +491,12 @@ export const getRaceTimerState = () =>
 
 export const setRaceClockStart = (centiseconds: number) =>
   invoke<RaceTimerState>("set_race_clock_start", { centiseconds });
+
+export const setRaceStage = (
+  stageType: PCType,
+  targetClockCentiseconds: number | null
+) =>
+  invoke<RaceTimerState>("set_race_stage", {
+    stageType,
+    targetClockCentiseconds,
+  });
@@ -9,9 +9,12 @@ import {
   useDeleteReference,
   useToggleControlZone,
 } from "../hooks/useReferences";
+import { useUndoEdit, useRedoEdit } from "../hooks/useEditHistory";
 import { ContextMenu } from "./ContextMenu";
 import { ConfirmDialog } from "./ConfirmDialog";
-import type { EventType, ReferenceEntry } from "../types";
+import { InputModal } from "./InputModal";
+import type { EventType, ReferenceEntry, UpdateReferenceRequest } from "../types";
+import { formatSpeed, parseSpeed } from "../utils/speed";
 
 interface PCEditorProps {
   raceId: number;
@@ -58,6 +61,11 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
   const [highlightedRow, setHighlightedRow] = useState<number | null>(null);
   const [editingRef, setEditingRef] = useState<ReferenceEntry | null>(null);
   const [deletingRef, setDeletingRef] = useState<ReferenceEntry | null>(null);
+  const [detailEdit, setDetailEdit] = useState<{
+    ref: ReferenceEntry;
+    field: "landmark" | "note";
+  } | null>(null);
+  const [distanceEdit, setDistanceEdit] = useState<ReferenceEntry | null>(null);
   const [contextMenu, setContextMenu] = useState<{
     x: number;
     y: number;
@@ -66,6 +74,8 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
   const [showEventModal, setShowEventModal] = useState(false);
   const [showExtraInputModal, setShowExtraInputModal] = useState(false);
   const [pendingEventType, setPendingEventType] = useState<EventType | null>(null);
+  const { mutate: undoEdit } = useUndoEdit();
+  const { mutate: redoEdit } = useRedoEdit();
 
   useEffect(() => {
     const updateScale = () => {
@@ -75,6 +85,23 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
     };
 
     const handleKeyDown = (e: KeyboardEvent) => {
+      // Ctrl+Z / Ctrl+Shift+Z / Ctrl+Y, text fields keep their own undo
+      const target = e.target as HTMLElement;
+      const typing = target.tagName === "INPUT" || target.tagName === "TEXTAREA";
+      if ((e.ctrlKey || e.metaKey) && !typing) {
+        const key = e.key.toLowerCase();
+        if (key === "z" && !e.shiftKey) {
+          e.preventDefault();
+          undoEdit();
+          return;
+        }
+        if ((key === "z" && e.shiftKey) || key === "y") {
+          e.preventDefault();
+          redoEdit();
+          return;
+        }
+      }
+
       if (e.key === "Escape") {
         if (contextMenu) {
           setContextMenu(null);
@@ -85,6 +112,10 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
           setPendingEventType(null);
         } else if (deletingRef) {
           setDeletingRef(null);
+        } else if (detailEdit) {
+          setDetailEdit(null);
+        } else if (distanceEdit) {
+          setDistanceEdit(null);
         } else {
           navigate({
             to: "/races/$raceId",
@@ -101,7 +132,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
       window.removeEventListener("resize", updateScale);
       window.removeEventListener("keydown", handleKeyDown);
     };
-  }, [navigate, raceId, contextMenu, showEventModal, showExtraInputModal, deletingRef]);
+  }, [navigate, raceId, contextMenu, showEventModal, showExtraInputModal, deletingRef, detailEdit, distanceEdit, undoEdit, redoEdit]);
 
   // Set default event type and speed based on references
   useEffect(() => {
@@ -112,7 +143,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
         setEventType("REF");
         // Initialize speed from last reference
         const lastRef = references[references.length - 1];
-        setSpeedInput(String(lastRef.speed));
+        setSpeedInput(formatSpeed(lastRef.speed));
       }
     }
   }, [references, editingRef]);
@@ -126,7 +157,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
       const cc = String(editingRef.centiseconds).padStart(2, "0");
       setTimeInput(`${h}${m}${s}${cc}`);
       setEventType(editingRef.event_type as EventType);
-      setSpeedInput(String(editingRef.speed));
+      setSpeedInput(formatSpeed(editingRef.speed));
       setExtraValue(editingRef.extra_value?.toString() || "");
     }
   }, [editingRef]);
@@ -186,7 +217,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
   const isFormValid = () => {
     if (timeInput.length !== 8) return false;
     if (!isTimeValid()) return false;
-    const speed = parseInt(speedInput);
+    const speed = parseSpeed(speedInput);
     if (isNaN(speed) || speed < 0) return false;
     if (needsExtraInput && !extraValue) return false;
     return true;
@@ -212,7 +243,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
     const canChangeSpeed = type === "CVT" || type === "CVD" || type === "CVR";
     if (!canChangeSpeed && references && references.length > 0) {
       const lastRef = references[references.length - 1];
-      setSpeedInput(String(lastRef.speed));
+      setSpeedInput(formatSpeed(lastRef.speed));
     }
   };
 
@@ -266,7 +297,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
     if (!isFormValid()) return;
 
     const { hours, minutes, seconds, centiseconds } = parseTimeInput(timeInput);
-    const speed = parseInt(speedInput) || 0;
+    const speed = parseSpeed(speedInput) || 0;
     const extra = extraValue ? parseFloat(extraValue) : undefined;
 
     if (editingRef) {
@@ -279,34 +310,11 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
         event_type: eventType,
         speed,
         extra_value: extra,
+        defined_by: editingRef.defined_by,
+        distance_meters: editingRef.distance_meters,
+        note: editingRef.note,
+        landmark: editingRef.landmark,
       });
-
-      // If editing a speed change event, propagate the new speed to subsequent entries
-      const isSpeedChangeEvent = eventType === "LAR" || eventType === "CVT" || eventType === "CVD" || eventType === "CVR";
-      if (isSpeedChangeEvent && references) {
-        const editingIndex = references.findIndex(r => r.id === editingRef.id);
-        if (editingIndex !== -1) {
-          // Update subsequent references until we hit another speed change event
-          for (let i = editingIndex + 1; i < references.length; i++) {
-            const ref = references[i];
-            const refIsSpeedChange = ref.event_type === "CVT" || ref.event_type === "CVD" || ref.event_type === "CVR";
-            if (refIsSpeedChange) {
-              break; // Stop at the next speed change
-            }
-            // Update this reference's speed to match the edited speed change
-            await updateReference.mutateAsync({
-              id: ref.id,
-              hours: ref.hours,
-              minutes: ref.minutes,
-              seconds: ref.seconds,
-              centiseconds: ref.centiseconds,
-              event_type: ref.event_type,
-              speed,
-              extra_value: ref.extra_value ?? undefined,
-            });
-          }
-        }
-      }
     } else {
       await createReference.mutateAsync({
         pc_id: pcId,
@@ -356,6 +364,47 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
     }
   };
 
+  // Saves a reference with some fields changed, keeping everything else as it is
+  const saveReferenceWith = async (ref: ReferenceEntry, changes: Partial<UpdateReferenceRequest>) => {
+    await updateReference.mutateAsync({
+      id: ref.id,
+      hours: ref.hours,
+      minutes: ref.minutes,
+      seconds: ref.seconds,
+      centiseconds: ref.centiseconds,
+      event_type: ref.event_type,
+      speed: ref.speed,
+      extra_value: ref.extra_value ?? undefined,
+      defined_by: ref.defined_by,
+      distance_meters: ref.distance_meters,
+      note: ref.note,
+      landmark: ref.landmark,
+      ...changes,
+    });
+    refetchReferences();
+  };
+
+  const handleSaveDetail = async (value: string) => {
+    if (!detailEdit) return;
+    await saveReferenceWith(detailEdit.ref, { [detailEdit.field]: value.trim() || null });
+    setDetailEdit(null);
+  };
+
+  // Distance is typed in km, the time of the reference is then computed by the backend
+  const handleSaveDistance = async (value: string) => {
+    if (!distanceEdit) return;
+    const km = parseFloat(value.replace(",", "."));
+    if (isNaN(km) || km < 0) return;
+    await saveReferenceWith(distanceEdit, {
+      defined_by: "distance",
+      distance_meters: Math.round(km * 1000 * 10) / 10,
+    });
+    setDistanceEdit(null);
+  };
+
+  const formatDistance = (meters: number | null) =>
+    meters === null ? "-" : `${(meters / 1000).toFixed(3).replace(".", ",")} km`;
+
   const handleToggleControlZone = async (ref: ReferenceEntry) => {
     await toggleControlZone.mutateAsync(ref.id);
     refetchReferences();
@@ -380,6 +429,10 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
       parts.push("ZC");
     }
 
+    if (ref.has_tulip) {
+      parts.push("TUL");
+    }
+
     return parts.length > 0 ? parts.join(" ") : "-";
   };
 
@@ -513,7 +566,7 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
                   value={speedInput}
                   onChange={(e) => {
                     if (!canEditSpeed) return;
-                    const val = e.target.value.replace(/\D/g, "");
+                    const val = e.target.value.replace(/[^\d.]/g, "");
                     setSpeedInput(val);
                   }}
                   disabled={!canEditSpeed}
@@ -572,14 +625,21 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
                 key={ref.id}
                 onClick={() => setHighlightedRow(highlightedRow === index ? null : index)}
                 onContextMenu={(e) => handleContextMenu(e, ref)}
+                title={[ref.landmark, ref.note].filter(Boolean).join(" · ") || undefined}
                 className={`flex gap-[30px] whitespace-nowrap py-[8px] -mx-[30px] px-[30px] cursor-pointer ${
                   highlightedRow === index ? "bg-[#3e61ff]" : "hover:bg-[#333]"
                 }`}
               >
-                <span className="w-[200px]">
-                  {formatTime(ref.hours, ref.minutes, ref.seconds, ref.centiseconds)}
+                <span className="w-[200px] flex flex-col leading-tight">
+                  {/* The computed value of the pair is dimmed */}
+                  <span className={ref.defined_by === "distance" ? "text-white/50" : ""}>
+                    {formatTime(ref.hours, ref.minutes, ref.seconds, ref.centiseconds)}
+                  </span>
+                  <span className={`text-[18px] ${ref.defined_by === "distance" ? "" : "text-white/50"}`}>
+                    {formatDistance(ref.distance_meters)}
+                  </span>
                 </span>
-                <span className="w-[90px]">{ref.speed}</span>
+                <span className="w-[90px]">{formatSpeed(ref.speed)}</span>
                 <span className="w-[100px]">{ref.event_type}</span>
                 <span className="w-[120px]">{formatInfo(ref)}</span>
               </div>
@@ -610,6 +670,23 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
                 label: "Editar",
                 onClick: () => setEditingRef(contextMenu.ref),
               },
+              contextMenu.ref.defined_by === "distance"
+                ? {
+                    label: "Definir por horario",
+                    onClick: () => saveReferenceWith(contextMenu.ref, { defined_by: "time" }),
+                  }
+                : {
+                    label: "Definir por distancia",
+                    onClick: () => setDistanceEdit(contextMenu.ref),
+                  },
+              {
+                label: "Punto de referencia",
+                onClick: () => setDetailEdit({ ref: contextMenu.ref, field: "landmark" }),
+              },
+              {
+                label: "Nota",
+                onClick: () => setDetailEdit({ ref: contextMenu.ref, field: "note" }),
+              },
               {
                 label: "Eliminar",
                 onClick: () => setDeletingRef(contextMenu.ref),
@@ -632,6 +709,32 @@ export function PCEditor({ raceId, pcId }: PCEditorProps) {
           />
         )}
 
+        {/* Landmark / Note Input */}
+        {detailEdit && (
+          <InputModal
+            title={detailEdit.field === "landmark" ? "Punto de referencia" : "Nota"}
+            placeholder={detailEdit.field === "landmark" ? "Ej: cartel km 23" : ""}
+            initialValue={detailEdit.ref[detailEdit.field] ?? ""}
+            onConfirm={handleSaveDetail}
+            onCancel={() => setDetailEdit(null)}
+          />
+        )}
+
+        {/* Distance Input */}
+        {distanceEdit && (
+          <InputModal
+            title="Distancia desde largada (km)"
+            placeholder="Ej: 3,250"
+            initialValue={
+              distanceEdit.distance_meters !== null
+                ? (distanceEdit.distance_meters / 1000).toFixed(3).replace(".", ",")
+                : ""
+            }
+            onConfirm={handleSaveDistance}
+            onCancel={() => setDistanceEdit(null)}
+          />
+        )}
+
         {/* Event Selection Modal */}
         {showEventModal && (
           <div className="absolute inset-0 bg-black/50 flex items-center justify-center z-50">
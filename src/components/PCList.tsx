@@ -86,7 +86,8 @@ export function PCList({ raceId }: PCListProps) {
   };
 
   const handleUpdateRaceName = async (name: string) => {
-    await updateRace.mutateAsync({ id: raceId, name });
+    if (!race) return;
+    await updateRace.mutateAsync({ ...race, name });
     setEditingRaceName(false);
   };
 
@@ -209,7 +210,7 @@ export function PCList({ raceId }: PCListProps) {
         {deletingPC && (
           <ConfirmDialog
             title="Eliminar PC"
-            message={`¿Estás seguro de que quieres eliminar PC ${deletingPC.pc_number}? Esta acción eliminará todas las referencias asociadas.`}
+            message={`¿Estás seguro de que quieres eliminar PC ${deletingPC.pc_number}? Irá a la papelera con sus referencias y podrás restaurarlo.`}
             confirmLabel="Eliminar"
             onConfirm={handleDeletePC}
             onCancel={() => setDeletingPC(null)}
@@ -67,13 +67,13 @@ export function RacesList() {
   };
 
   const handleCreateRace = async (name: string) => {
-    await createRace.mutateAsync(name);
+    await createRace.mutateAsync({ name });
     setShowCreateModal(false);
   };
 
   const handleUpdateRace = async (name: string) => {
     if (editingRace) {
-      await updateRace.mutateAsync({ id: editingRace.id, name });
+      await updateRace.mutateAsync({ ...editingRace, name });
       setEditingRace(null);
     }
   };
@@ -190,7 +190,7 @@ export function RacesList() {
         {deletingRace && (
           <ConfirmDialog
             title="Eliminar Carrera"
-            message={`¿Estás seguro de que quieres eliminar "${deletingRace.name}"? Esta acción eliminará todos los PCs y referencias asociados.`}
+            message={`¿Estás seguro de que quieres eliminar "${deletingRace.name}"? Irá a la papelera con sus PCs y referencias y podrás restaurarla.`}
             confirmLabel="Eliminar"
             onConfirm={handleDeleteRace}
             onCancel={() => setDeletingRace(null)}
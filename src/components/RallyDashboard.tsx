@@ -14,10 +14,12 @@ import {
   resetOdometer,
   fullResetRaceTimer,
   setRaceClockStart,
+  setRaceStage,
   getNextPc,
   getReferencesByPc,
 } from "../api/tauri";
 import type { ReferenceEntry, RaceTimerState } from "../types";
+import { formatSpeed } from "../utils/speed";
 
 interface RecordedSnapshot {
   referenceIndex: number;
@@ -55,6 +57,8 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
     diff_snapshot: 0,
     odometer_meters: 0,
     race_clock_centiseconds: 0,
+    stage_type: "regularity",
+    target_remaining_centiseconds: null,
   });
 
   // Load data
@@ -172,7 +176,7 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
   // Get speed at a specific speed change index
   const getSpeedChangeAt = (changeIndex: number): string | null => {
     if (changeIndex < 0 || changeIndex >= speedChanges.length) return null;
-    return String(speedChanges[changeIndex].speed);
+    return formatSpeed(speedChanges[changeIndex].speed);
   };
 
   // Display speeds: 1 before, current, 2 after (based on speed changes, not references)
@@ -186,6 +190,19 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
     ? speedChanges[currentSpeedChangeIndex].speed
     : 0;
 
+  // Tell Rust which kind of stage this PC is (liaisons run against a target time)
+  useEffect(() => {
+    if (isInitialized && pc) {
+      const target =
+        pc.pc_type === "liaison" &&
+        pc.scheduled_start_centiseconds !== null &&
+        pc.target_time_centiseconds !== null
+          ? pc.scheduled_start_centiseconds + pc.target_time_centiseconds
+          : null;
+      setRaceStage(pc.pc_type, target);
+    }
+  }, [pc, isInitialized]);
+
   // Update Rust with current speed when it changes or after initialization
   useEffect(() => {
     if (isInitialized) {
@@ -257,6 +274,10 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
 
             // Start the race timer
             toggleRaceTimer();
+          } else if (pc?.pc_type === "liaison" && pc.scheduled_start_centiseconds !== null) {
+            // Liaisons have no references, the clock starts at the scheduled start
+            setRaceClockStart(pc.scheduled_start_centiseconds);
+            toggleRaceTimer();
           }
         }
       } else if (e.key === "1") {
@@ -300,7 +321,7 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
       window.removeEventListener("resize", updateScale);
       window.removeEventListener("keydown", handleKeyDown);
     };
-  }, [navigate, raceId, references, showDistanceModal, odometerDistance, timerState.is_running, recordedSnapshots]);
+  }, [navigate, raceId, pc, references, showDistanceModal, odometerDistance, timerState.is_running, recordedSnapshots]);
 
   // Helper to format time
   const formatTime = (ref: ReferenceEntry) => {
@@ -330,8 +351,8 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
     const diffCs = expectedCs - recordedCs;
 
     // Calculate difference in meters based on current speed
-    // speed is in km/h, convert to m/cs: (speed / 3.6) / 100 = speed / 360
-    const diffMts = (currentRef.speed / 360) * diffCs;
+    // speed is in thousandths of km/h, convert to m/cs: (speed / 1000 / 3.6) / 100 = speed / 360000
+    const diffMts = (currentRef.speed / 360000) * diffCs;
 
     // Calculate recommended factor using raw_meters captured at the moment of odometer tick
     // Compensate for pilot timing error:
@@ -359,7 +380,7 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
     // Debug log for validating calculations
     console.log("=== DEBUG ===", {
       referencia: idx,
-      velocidad_kmh: currentRef.speed,
+      velocidad_kmh: currentRef.speed / 1000,
       tiempo_esperado_cs: expectedCs,
       tiempo_registrado_cs: recordedCs,
       diff_cs: diffCs,
@@ -383,11 +404,17 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
   // Get the reference data for display
   const referenceRows = references?.map((ref) => ({
     time: formatTime(ref),
-    vel: String(ref.speed),
+    vel: formatSpeed(ref.speed),
     evt: ref.event_type,
     det: ref.is_control_zone ? "ZC" : "-",
   })) || [];
 
+  // Landmark and note of the upcoming reference, so the navigator knows what to look for
+  const upcomingRef = references?.[currentIndex];
+  const upcomingDetail = upcomingRef
+    ? [upcomingRef.landmark, upcomingRef.note].filter(Boolean).join(" · ")
+    : "";
+
   // Extract values from timer state for display
   const { corrected_meters, correction_factor, diff_snapshot, odometer_meters, race_clock_centiseconds } = timerState;
 
@@ -402,9 +429,16 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
 
   // Format countdown to next PC (centiseconds to MM:SS)
   const formatCountdown = (): string => {
-    if (nextPcLarCentiseconds === null) return "--:--";
-    const correctedClock = race_clock_centiseconds + clockCorrectionCs;
-    const remainingCentiseconds = nextPcLarCentiseconds - correctedClock;
+    let remainingCentiseconds: number;
+    if (timerState.target_remaining_centiseconds !== null) {
+      // Liaison: count down to the stage target time
+      remainingCentiseconds = timerState.target_remaining_centiseconds - clockCorrectionCs;
+    } else if (nextPcLarCentiseconds !== null) {
+      const correctedClock = race_clock_centiseconds + clockCorrectionCs;
+      remainingCentiseconds = nextPcLarCentiseconds - correctedClock;
+    } else {
+      return "--:--";
+    }
     if (remainingCentiseconds <= 0) return "00:00";
     const totalSeconds = Math.floor(remainingCentiseconds / 100);
     const minutes = Math.floor(totalSeconds / 60);
@@ -516,6 +550,9 @@ export function RallyDashboard({ raceId, pcId }: RallyDashboardProps) {
           <p className="absolute left-[383px] -translate-x-1/2 top-[33px] text-[20px]">m</p>
           <p className="absolute left-[514px] -translate-x-1/2 top-[33px] text-[20px]">cm</p>
           <p className="absolute left-1/2 -translate-x-1/2 top-[57px] text-[110px]">+00000.0</p>
+          <p className="absolute left-1/2 -translate-x-1/2 top-[190px] w-[520px] text-[24px] truncate">
+            {upcomingDetail}
+          </p>
         </div>
 
         {/* PC Section */}
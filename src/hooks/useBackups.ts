@@ -0,0 +1,56 @@
+import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
+import * as api from "../api/tauri";
+import type { RaceBundleImportMode } from "../types";
+
+export const useBackups = () =>
+  useQuery({
+    queryKey: ["backups"],
+    queryFn: api.getBackups,
+  });
+
+export const useBackupRaces = (fileName: string | null) =>
+  useQuery({
+    queryKey: ["backupRaces", fileName],
+    queryFn: () => api.getBackupRaces(fileName!),
+    enabled: fileName !== null,
+  });
+
+export const useCreateBackup = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: api.createBackup,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["backups"] });
+    },
+  });
+};
+
+// A restore replaces everything, so every query is refreshed
+export const useRestoreBackup = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: api.restoreBackup,
+    onSuccess: () => {
+      queryClient.invalidateQueries();
+    },
+  });
+};
+
+export const useExtractBackupRace = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({
+      fileName,
+      raceId,
+      mode,
+    }: {
+      fileName: string;
+      raceId: number;
+      mode?: RaceBundleImportMode;
+    }) => api.extractBackupRace(fileName, raceId, mode),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["races"] });
+      queryClient.invalidateQueries({ queryKey: ["backups"] });
+    },
+  });
+};
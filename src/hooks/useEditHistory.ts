@@ -0,0 +1,25 @@
+import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
+import * as api from "../api/tauri";
+
+export const useEditHistory = () =>
+  useQuery({
+    queryKey: ["editHistory"],
+    queryFn: () => api.getEditHistory(),
+  });
+
+// Undo and redo may touch any race, PC or reference, so every query is refreshed
+const useStepEditHistory = (step: () => ReturnType<typeof api.undoEdit>) => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: step,
+    onSuccess: (entry) => {
+      if (entry) {
+        queryClient.invalidateQueries();
+      }
+    },
+  });
+};
+
+export const useUndoEdit = () => useStepEditHistory(api.undoEdit);
+
+export const useRedoEdit = () => useStepEditHistory(api.redoEdit);
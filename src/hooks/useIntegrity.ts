@@ -0,0 +1,19 @@
+import { useMutation, useQueryClient } from "@tanstack/react-query";
+import * as api from "../api/tauri";
+
+// A check reads the whole file, so it only runs when asked for
+export const useCheckDatabase = () =>
+  useMutation({
+    mutationFn: api.checkDatabase,
+  });
+
+// Repairs may delete rows and renumber references anywhere
+export const useRepairDatabase = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: api.repairDatabase,
+    onSuccess: () => {
+      queryClient.invalidateQueries();
+    },
+  });
+};
@@ -1,5 +1,6 @@
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
 import * as api from "../api/tauri";
+import type { UpdatePCRequest } from "../types";
 
 export const usePCsByRace = (raceId: number) =>
   useQuery({
@@ -25,12 +26,24 @@ export const useCreatePC = () => {
   });
 };
 
+export const useUpdatePC = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: (request: UpdatePCRequest) => api.updatePc(request),
+    onSuccess: (updatedPc) => {
+      queryClient.invalidateQueries({ queryKey: ["pcs", updatedPc.race_id] });
+      queryClient.invalidateQueries({ queryKey: ["pc", updatedPc.id] });
+    },
+  });
+};
+
 export const useDeletePC = () => {
   const queryClient = useQueryClient();
   return useMutation({
     mutationFn: api.deletePc,
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ["pcs"] });
+      queryClient.invalidateQueries({ queryKey: ["trash"] });
     },
   });
 };
@@ -1,10 +1,11 @@
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
 import * as api from "../api/tauri";
+import type { RaceSort, UpdateRaceRequest } from "../types";
 
-export const useRaces = () =>
+export const useRaces = (search?: string, sort?: RaceSort) =>
   useQuery({
-    queryKey: ["races"],
-    queryFn: api.getAllRaces,
+    queryKey: ["races", search ?? "", sort ?? "recent"],
+    queryFn: () => api.getAllRaces(search, sort),
   });
 
 export const useRace = (id: number) =>
@@ -27,8 +28,7 @@ export const useCreateRace = () => {
 export const useUpdateRace = () => {
   const queryClient = useQueryClient();
   return useMutation({
-    mutationFn: ({ id, name }: { id: number; name: string }) =>
-      api.updateRace(id, name),
+    mutationFn: (request: UpdateRaceRequest) => api.updateRace(request),
     onSuccess: (_, variables) => {
       queryClient.invalidateQueries({ queryKey: ["races"] });
       queryClient.invalidateQueries({ queryKey: ["race", variables.id] });
@@ -42,6 +42,27 @@ export const useDeleteRace = () => {
     mutationFn: api.deleteRace,
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ["races"] });
+      queryClient.invalidateQueries({ queryKey: ["trash"] });
+    },
+  });
+};
+
+export const useSetRaceLock = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({
+      raceId,
+      locked,
+      reason,
+    }: {
+      raceId: number;
+      locked: boolean;
+      reason?: string;
+    }) =>
+      locked ? api.lockRace(raceId, reason) : api.unlockRace(raceId, reason),
+    onSuccess: (race) => {
+      queryClient.invalidateQueries({ queryKey: ["races"] });
+      queryClient.invalidateQueries({ queryKey: ["race", race.id] });
     },
   });
 };
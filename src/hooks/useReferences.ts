@@ -9,6 +9,13 @@ export const useReferencesByPC = (pcId: number) =>
     enabled: pcId > 0,
   });
 
+export const useRouteIntervals = (pcId: number) =>
+  useQuery({
+    queryKey: ["references", pcId, "intervals"],
+    queryFn: () => api.getRouteIntervals(pcId),
+    enabled: pcId > 0,
+  });
+
 export const useCreateReference = () => {
   const queryClient = useQueryClient();
   return useMutation({
@@ -31,6 +38,19 @@ export const useUpdateReference = () => {
   });
 };
 
+export const useSetSegmentSpeed = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: ({ id, speed }: { id: number; speed: number }) =>
+      api.setSegmentSpeed(id, speed),
+    onSuccess: (refs) => {
+      if (refs.length > 0) {
+        queryClient.invalidateQueries({ queryKey: ["references", refs[0].pc_id] });
+      }
+    },
+  });
+};
+
 export const useDeleteReference = () => {
   const queryClient = useQueryClient();
   return useMutation({
@@ -0,0 +1,46 @@
+import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
+import * as api from "../api/tauri";
+
+export const useTrash = () =>
+  useQuery({
+    queryKey: ["trash"],
+    queryFn: api.getTrash,
+  });
+
+export const useTrashRetentionDays = () =>
+  useQuery({
+    queryKey: ["trashRetentionDays"],
+    queryFn: api.getTrashRetentionDays,
+  });
+
+// Restoring and purging move races and PCs in and out of every list
+const useTrashMutation = <T, R>(mutationFn: (arg: T) => Promise<R>) => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn,
+    onSuccess: () => {
+      queryClient.invalidateQueries();
+    },
+  });
+};
+
+export const useRestoreRace = () => useTrashMutation(api.restoreRace);
+
+export const useRestorePc = () => useTrashMutation(api.restorePc);
+
+export const usePurgeRace = () => useTrashMutation(api.purgeRace);
+
+export const usePurgePc = () => useTrashMutation(api.purgePc);
+
+export const useEmptyTrash = () => useTrashMutation(api.emptyTrash);
+
+export const useSetTrashRetentionDays = () => {
+  const queryClient = useQueryClient();
+  return useMutation({
+    mutationFn: api.setTrashRetentionDays,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ["trashRetentionDays"] });
+      queryClient.invalidateQueries({ queryKey: ["trash"] });
+    },
+  });
+};
@@ -1,16 +1,81 @@
-export interface Race {
+export interface RaceMetadata {
+  event_date?: string | null; // YYYY-MM-DD
+  event_end_date?: string | null;
+  location?: string | null;
+  organizer?: string | null;
+  category?: string | null;
+  car_number?: string | null;
+  driver_name?: string | null;
+  navigator_name?: string | null;
+  notes?: string | null;
+}
+
+export interface Race extends RaceMetadata {
   id: number;
+  uid: string; // Stable identity, shared by copies imported from a bundle
   name: string;
+  locked_at: string | null; // Set while the roadbook is locked for competition
+  deleted_at: string | null; // Set while the race is in the trash
   created_at: string;
 }
 
+export interface RaceLockEvent {
+  id: number;
+  race_id: number;
+  action: "lock" | "unlock";
+  reason: string | null;
+  created_at: string;
+}
+
+// Rejection of the commands that refuse to edit a locked race. Other
+// commands reject with a plain message string.
+export interface CommandError {
+  kind: "race_locked" | "other";
+  message: string;
+}
+
+export interface CreateRaceRequest extends RaceMetadata {
+  name: string;
+}
+
+export interface UpdateRaceRequest extends RaceMetadata {
+  id: number;
+  name: string;
+}
+
+export type RaceSort = "recent" | "name" | "event_date";
+
+export type PCType = "regularity" | "liaison" | "super_special";
+
 export interface PC {
   id: number;
   race_id: number;
   pc_number: number;
+  name: string | null;
+  pc_type: PCType;
+  start_location: string | null;
+  scheduled_start_centiseconds: number | null; // Time of day
+  target_time_centiseconds: number | null; // Time allowed (liaisons)
+  total_distance_meters: number | null;
+  notes: string | null;
+  deleted_at: string | null; // Set while the PC is in the trash
   created_at: string;
 }
 
+export interface UpdatePCRequest {
+  id: number;
+  name?: string | null;
+  pc_type: PCType;
+  start_location?: string | null;
+  scheduled_start_centiseconds?: number | null;
+  target_time_centiseconds?: number | null;
+  total_distance_meters?: number | null;
+  notes?: string | null;
+}
+
+// A reference is given either by passage time or by distance from the start
+export type ReferenceDefinition = "time" | "distance";
+
 export interface ReferenceEntry {
   id: number;
   pc_id: number;
@@ -19,10 +84,24 @@ export interface ReferenceEntry {
   seconds: number;
   centiseconds: number;
   event_type: EventType;
-  speed: number;
+  speed: number; // Thousandths of km/h
   extra_value: number | null;
   is_control_zone: boolean;
   order_index: number;
+  defined_by: ReferenceDefinition;
+  distance_meters: number | null; // From the LAR, computed when defined by time
+  note: string | null;
+  landmark: string | null; // "cartel km 23", "puente", "T a la derecha"
+  has_tulip: boolean;
+  created_at: string;
+}
+
+export interface ReferenceTulip {
+  id: number;
+  reference_id: number;
+  mime_type: string;
+  data: number[]; // Image bytes
+  description: string | null;
   created_at: string;
 }
 
@@ -35,8 +114,12 @@ export interface CreateReferenceRequest {
   seconds: number;
   centiseconds: number;
   event_type: EventType;
-  speed: number;
+  speed: number; // Thousandths of km/h
   extra_value?: number;
+  defined_by?: ReferenceDefinition;
+  distance_meters?: number | null;
+  note?: string | null;
+  landmark?: string | null;
 }
 
 export interface UpdateReferenceRequest {
@@ -46,17 +129,420 @@ export interface UpdateReferenceRequest {
   seconds: number;
   centiseconds: number;
   event_type: EventType;
-  speed: number;
+  speed: number; // Thousandths of km/h
   extra_value?: number;
+  defined_by?: ReferenceDefinition;
+  distance_meters?: number | null;
+  note?: string | null;
+  landmark?: string | null;
+}
+
+export interface RouteInterval {
+  reference_id: number;
+  event_type: EventType;
+  time_centiseconds: number;
+  distance_meters: number; // Cumulative from the LAR
+  delta_centiseconds: number | null; // Since the previous reference
+  delta_meters: number | null;
+  segment_speed: number; // Thousandths of km/h driven to reach this reference
+  time_shift_centiseconds: number; // ADL positive, ATR negative
+}
+
+export interface MarchTableOptions {
+  step_by: "distance" | "time";
+  step: number; // Meters or seconds
+}
+
+// One line of a "tabla de marcha": a step mark or a reference
+export interface MarchRow {
+  time_centiseconds: number;
+  distance_meters: number;
+  speed: number; // Thousandths of km/h driven from this point on
+  reference_id: number | null;
+  event_type: EventType | null;
+  landmark: string | null;
+  is_speed_change: boolean;
+}
+
+// Roadbook field names, mapped to the column headers of a file. The time
+// can be a single column or split in hours, minutes, seconds and centiseconds.
+export type RoadbookField =
+  | "pc"
+  | "time"
+  | "hours"
+  | "minutes"
+  | "seconds"
+  | "centiseconds"
+  | "event_type"
+  | "speed"
+  | "extra_value"
+  | "defined_by"
+  | "distance_meters"
+  | "control_zone"
+  | "notes";
+
+export type RoadbookMapping = Partial<Record<RoadbookField, string>>;
+
+export interface RoadbookLineError {
+  line: number; // The header is line 1
+  message: string;
+}
+
+export interface RoadbookImportReport {
+  applied: boolean; // False for previews and files with unreadable lines
+  mapping: RoadbookMapping;
+  rows_read: number;
+  references_created: number;
+  pcs_created: number[];
+  errors: RoadbookLineError[];
+  validation: ValidationReport[];
+}
+
+export interface XlsxSheetPreview {
+  name: string;
+  rows: string[][]; // First rows as text, to pick the header row
+}
+
+export interface XlsxImportOptions {
+  sheet_name?: string; // First sheet when missing
+  header_row?: number; // 1-based sheet row, first used row when missing
+  mapping?: RoadbookMapping;
+  replace_existing?: boolean;
+}
+
+// Column mapping saved for an organiser whose spreadsheets share a layout
+export interface ImportTemplate {
+  id: number;
+  name: string;
+  organizer: string | null;
+  sheet_name: string | null;
+  header_row: number | null;
+  mapping: RoadbookMapping;
+  created_at: string;
+}
+
+export interface SaveImportTemplateRequest {
+  id?: number; // Updates the template when set
+  name: string;
+  organizer?: string | null;
+  sheet_name?: string | null;
+  header_row?: number | null;
+  mapping: RoadbookMapping;
+}
+
+// A line of a PDF roadbook read as a reference
+export interface PdfCandidate {
+  line: number; // Line of the extracted text, counted across pages
+  page: number;
+  text: string;
+  pc_number: number;
+  reference: CreateReferenceRequest; // pc_id is 0 when the PC would be created
+  confidence: number; // 0 to 1
+  issues: string[];
+}
+
+export type ReferenceDiffStatus =
+  | "added"
+  | "changed"
+  | "unchanged"
+  | "removed" // Missing from the file, replacing
+  | "kept"; // Missing from the file, appending
+
+export interface ReferenceDiff {
+  pc_number: number;
+  status: ReferenceDiffStatus;
+  existing: ReferenceEntry | null;
+  proposed: CreateReferenceRequest | null;
+  line: number | null;
+}
+
+export interface PdfImportOptions {
+  min_confidence?: number; // Defaults to 0.6
+  lines?: number[]; // Picked by hand, overrides the confidence
+  replace_existing?: boolean;
+}
+
+export interface PdfImportPreview {
+  pages: number;
+  lines_read: number;
+  candidates: PdfCandidate[]; // Most confident first
+  accepted_lines: number[];
+  diff: ReferenceDiff[];
+  report: RoadbookImportReport; // Dry run of the accepted candidates
+}
+
+// How to import a bundle whose race already exists
+export type RaceBundleImportMode = "replace" | "merge" | "copy";
+
+// How a PC of the bundle compares with the race already here. "local" PCs
+// are only here: "merge" keeps them, "replace" moves them to the trash.
+export interface BundlePCConflict {
+  pc_number: number;
+  status: "new" | "changed" | "same" | "local";
+  local_references: number;
+  bundle_references: number;
+}
+
+export interface RaceBundleImportReport {
+  applied: boolean;
+  race: Race | null;
+  conflict: Race | null; // Existing race with the same uid, when no mode was given
+  pc_conflicts: BundlePCConflict[]; // Set with the conflict, by PC number
+  pcs_created: number[];
+  pcs_replaced: number[];
+  references_created: number;
+  settings_imported: number;
+}
+
+// One code of a race sent as a series of QR codes
+export interface QrChunk {
+  index: number; // 1-based
+  total: number;
+  payload: string; // Text held by the code
+  svg: string;
+}
+
+// How far the scanning of a QR series got
+export interface QrTransferStatus {
+  series_id: string;
+  total: number;
+  received: number[];
+  missing: number[];
+  complete: boolean; // Every code is there and the bundle checked out
+  race_name: string | null;
+}
+
+export type BackupReason =
+  | "startup"
+  | "before_migration"
+  | "before_import"
+  | "race_locked"
+  | "periodic"
+  | "before_restore"
+  | "before_repair"
+  | "manual";
+
+// A copy of the database in the backups folder
+export interface BackupInfo {
+  file_name: string;
+  reason: BackupReason;
+  created_at: string; // UTC
+  size_bytes: number;
+}
+
+export type IntegrityIssueKind =
+  | "integrity" // SQLite found the file damaged
+  | "foreign_key" // Rows pointing at a race or PC that is gone
+  | "orphan_reference" // References of a PC that is gone
+  | "duplicate_order"
+  | "order_gap";
+
+// Something wrong found by the database check
+export interface IntegrityIssue {
+  kind: IntegrityIssueKind;
+  message: string;
+  race_id: number | null;
+  pc_id: number | null;
+  rows: number;
+  repairable: boolean; // Fixed by repairDatabase without losing data in use
+}
+
+export interface IntegrityReport {
+  ok: boolean;
+  issues: IntegrityIssue[]; // What is still wrong
+  repaired: IntegrityIssue[]; // What a repair fixed, empty for a check
+  backup: BackupInfo | null; // Taken before repairing
+}
+
+// A race as it is in a backup, trashed ones included
+export interface BackupRace {
+  id: number;
+  uid: string;
+  name: string;
+  event_date: string | null;
+  pc_count: number;
+  reference_count: number;
+  locked_at: string | null;
+  deleted_at: string | null;
+}
+
+// A race or PC in the trash. A trashed race keeps its PCs; PCs of a
+// trashed race are not listed on their own.
+export interface TrashItem {
+  kind: "race" | "pc";
+  id: number;
+  race_id: number;
+  race_name: string;
+  pc_number: number | null;
+  pc_name: string | null;
+  pc_count: number;
+  reference_count: number;
+  deleted_at: string;
+  purge_at: string | null; // Null when the trash is only emptied by hand
+}
+
+// An edit that can be undone, or redone once undone
+export interface EditHistoryEntry {
+  id: number;
+  action: string; // Command that made the edit, "delete_reference"
+  race_id: number | null;
+  undone: boolean;
+  created_at: string;
+}
+
+// A recorded change: the race (pc_id null) or one PC as it was afterwards
+export interface Revision {
+  id: number;
+  race_id: number;
+  pc_id: number | null;
+  action: string; // Command that made the change
+  created_at: string;
+}
+
+export interface PCSnapshot {
+  pc: PC;
+  references: ReferenceEntry[];
+  // null in revisions recorded before tulips were kept
+  tulips: ReferenceTulip[] | null;
+}
+
+export interface FieldChange {
+  field: string;
+  before: unknown;
+  after: unknown;
+}
+
+export interface ReferenceChange {
+  reference_id: number;
+  status: "added" | "removed" | "changed";
+  before: ReferenceEntry | null;
+  after: ReferenceEntry | null;
+  fields: FieldChange[]; // Only for "changed"
+}
+
+export interface PCRevisionDiff {
+  from: Revision;
+  to: Revision | null; // Null when compared with the current state
+  pc_fields: FieldChange[];
+  references: ReferenceChange[];
+}
+
+// One change of an organiser bulletin. PCs are given by number and references
+// by their 1-based position in the PC. Operations run in order, so positions
+// refer to the PC as left by the previous ones.
+export type BulletinOperation =
+  // Distance-defined references in the range become defined by their new time
+  | {
+      kind: "shift_times";
+      pc_number: number;
+      from: number;
+      to: number;
+      offset_centiseconds: number;
+    }
+  | { kind: "set_speed"; pc_number: number; position: number; speed: number }
+  | {
+      kind: "insert_reference";
+      pc_number: number;
+      position: number; // One past the last reference appends
+      reference: CreateReferenceRequest; // pc_id is ignored
+    }
+  | { kind: "remove_reference"; pc_number: number; position: number }
+  | {
+      kind: "set_control_zone";
+      pc_number: number;
+      from: number;
+      to: number;
+      is_control_zone: boolean;
+    };
+
+export type BulletinStatus = "draft" | "applied" | "reverted";
+
+export interface Bulletin {
+  id: number;
+  race_id: number;
+  name: string;
+  operations: BulletinOperation[];
+  status: BulletinStatus;
+  applied_at: string | null;
+  reverted_at: string | null;
+  created_at: string;
+}
+
+export interface SaveBulletinRequest {
+  id?: number; // Updates the draft when set
+  race_id: number;
+  name: string;
+  operations: BulletinOperation[];
+}
+
+export interface BulletinPCChange {
+  pc_id: number;
+  pc_number: number;
+  references: ReferenceChange[];
+  validation: ValidationReport;
+}
+
+export interface BulletinPreview {
+  bulletin: Bulletin;
+  applied: boolean;
+  pcs: BulletinPCChange[];
+}
+
+export interface DuplicateOptions {
+  time_offset_centiseconds?: number;
+  speed_scale?: number; // > 0, keeps the distances so the times change
+}
+
+export type ValidationSeverity = "error" | "warning";
+
+export interface ValidationIssue {
+  pc_id: number;
+  reference_id: number | null;
+  severity: ValidationSeverity;
+  code: string;
+  message: string;
+}
+
+export interface ValidationReport {
+  pc_id: number;
+  pc_number: number;
+  error_count: number;
+  warning_count: number;
+  issues: ValidationIssue[];
+}
+
+// Roadbook checksum, to confirm two machines loaded the same references
+export interface PCChecksum {
+  pc_id: number;
+  pc_number: number;
+  reference_count: number;
+  hash: string; // SHA-256, hex
+  fingerprint: string; // Short form to read aloud, "K7QD-3M9X"
+}
+
+export interface RaceChecksum {
+  race_id: number;
+  hash: string;
+  fingerprint: string;
+  pcs: PCChecksum[];
+}
+
+export interface PCChecksumComparison {
+  pc_number: number;
+  local: string | null;
+  other: string | null; // Fingerprint read from the other machine
+  matches: boolean;
 }
 
 export interface RaceTimerState {
   raw_meters: number;
   corrected_meters: number;
   correction_factor: number;
-  current_speed: number;
+  current_speed: number; // Thousandths of km/h
   is_running: boolean;
   diff_snapshot: number;
   odometer_meters: number;
   race_clock_centiseconds: number;
+  stage_type: PCType;
+  target_remaining_centiseconds: number | null;
 }
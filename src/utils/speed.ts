@@ -0,0 +1,7 @@
+// Speeds travel between frontend and backend as thousandths of km/h
+// (47.5 km/h is 47500), so decimal averages are stored exactly.
+
+export const parseSpeed = (input: string): number =>
+  Math.round(parseFloat(input.replace(",", ".")) * 1000);
+
+export const formatSpeed = (speed: number): string => String(speed / 1000);